@@ -0,0 +1,87 @@
+#![allow(dead_code)]
+
+use std::io;
+
+use error_def::ErrorDef;
+
+#[derive(ErrorDef)]
+#[error_def(description)]
+pub enum ExampleError {
+    #[error_def(short = "Unit-like variant")]
+    AVariant,
+    #[error_def(short = "Unit-like variant", long("A more verbose description"))]
+    AVariantWithALongDescription,
+    #[error_def(short = "Variant with args", long("flim is {}. flam is {}.", flim, flam))]
+    AVariantWithArgs {
+        flim: u32,
+        flam: u32,
+    },
+    #[error_def(short = "Variant with a cause", long("self.cause() would return Some({})", cause))]
+    AVariantWithACause {
+        blah: bool,
+        #[from] cause: io::Error,
+    },
+    #[error_def(short = "This variant can be made `From` an `io::Error`")]
+    AVariantWithJustACause {
+        #[from] blah: io::Error,
+    },
+    #[error_def(short = "Out of range", long("{} is not below {}", 0, 1))]
+    #[exit_code = 65]
+    ATupleVariant(u32, u32),
+    #[error_def(short = "Bad number", long("on line {}", line))]
+    AVariantWithDefaults {
+        #[from] cause: std::num::ParseIntError,
+        #[default = 1] line: u32,
+    },
+    #[error_def(short = "Formatting failed")]
+    #[transparent]
+    ATransparentVariant(#[from] std::fmt::Error),
+}
+
+#[test]
+#[allow(deprecated)]
+fn test() {
+    use std::error::Error;
+
+    let e = ExampleError::AVariant;
+    assert_eq!(&format!("{:?}", e), "ExampleError::AVariant /* Unit-like variant */");
+    assert_eq!(&format!("{:#}", e), "Unit-like variant");
+    assert_eq!(e.description(), "Unit-like variant");
+    assert!(e.cause().is_none());
+
+    let e = ExampleError::AVariantWithALongDescription;
+    assert_eq!(&format!("{:#}", e), "Unit-like variant. A more verbose description");
+
+    let e = ExampleError::AVariantWithArgs { flim: 123u32, flam: 456u32 };
+    assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithArgs { flim: 123, flam: 456 } /* Variant with args. flim is 123. flam is 456. */");
+    assert_eq!(&format!("{:#}", e), "Variant with args. flim is 123. flam is 456.");
+    assert!(e.cause().is_none());
+
+    let e = ExampleError::AVariantWithACause { blah: true, cause: io::Error::new(io::ErrorKind::PermissionDenied, "Yo you can't do that!") };
+    assert_eq!(&format!("{:#}", e), "Variant with a cause. self.cause() would return Some(Yo you can't do that!). Yo you can't do that!");
+    assert_eq!(e.description(), "Variant with a cause");
+    assert_eq!(e.cause().unwrap().to_string(), "Yo you can't do that!");
+
+    let e = ExampleError::from(io::Error::new(io::ErrorKind::PermissionDenied, "You still can't do that!"));
+    match e {
+        ExampleError::AVariantWithJustACause { .. } => (),
+        _ => panic!("wtf?"),
+    };
+    assert_eq!(&format!("{}", e), "This variant can be made `From` an `io::Error`");
+    assert_eq!(e.cause().unwrap().to_string(), "You still can't do that!");
+
+    let e = ExampleError::ATupleVariant(7, 5);
+    assert_eq!(&format!("{:?}", e), "ExampleError::ATupleVariant(7, 5) /* Out of range. 7 is not below 5 */");
+    assert_eq!(e.exit_code(), 65);
+    assert_eq!(ExampleError::AVariant.exit_code(), 1);
+
+    let e = ExampleError::from("x".parse::<u32>().unwrap_err());
+    assert_eq!(&format!("{:#}", e), "Bad number. on line 1. invalid digit found in string");
+
+    let e = ExampleError::from(std::fmt::Error);
+    assert_eq!(&format!("{:#}", e), "an error occurred when formatting an argument");
+    assert!(e.source().is_none());
+}
+
+fn main() {
+}
@@ -1,23 +1,27 @@
-#![feature(plugin)]
-#![plugin(error_def)]
 #![allow(dead_code)]
 
+use std::fmt;
 use std::io;
 
-error_def! ExampleError {
-    AVariant                     => "Unit-like variant",
-    AVariantWithALongDescription => "Unit-like variant" ("A more verbose description"),
-    AVariantWithArgs {
-        flim: u32,
-        flam: u32,
-    } => "Variant with args" ("This is a format string. flim is {}. flam is {}.", flim, flam),
-    AVariantWithACause {
-        blah: bool,
-        #[from] cause: io::Error,
-    } => "Variant with a cause" ("self.cause() would return Some({})", cause),
-    AVariantWithJustACause {
-        #[from] blah: io::Error,
-    } => "This variant can be made `From` an `io::Error`"
+use error_def::error_def;
+
+error_def! {
+    #[error_def(description)]
+    ExampleError {
+        AVariant                     => "Unit-like variant",
+        AVariantWithALongDescription => "Unit-like variant" ("A more verbose description"),
+        AVariantWithArgs {
+            flim: u32,
+            flam: u32,
+        } => "Variant with args" ("This is a format string. flim is {}. flam is {}.", flim, flam),
+        AVariantWithACause {
+            blah: bool,
+            #[from] cause: io::Error,
+        } => "Variant with a cause" ("self.cause() would return Some({})", cause),
+        AVariantWithJustACause {
+            #[from] blah: io::Error,
+        } => "This variant can be made `From` an `io::Error`"
+    }
 }
 
 /* Expands (roughly) to
@@ -111,42 +115,713 @@ impl From<io::Error> for ExampleError {
 */
 
 #[test]
+#[allow(deprecated)]
 fn test() {
     use std::error::Error;
 
     let e = ExampleError::AVariant;
     assert_eq!(&format!("{:?}", e), "ExampleError::AVariant /* Unit-like variant */");
-    assert_eq!(&format!("{}", e), "Unit-like variant");
+    assert_eq!(&format!("{:#}", e), "Unit-like variant");
     assert_eq!(e.description(), "Unit-like variant");
     assert!(e.cause().is_none());
 
     let e = ExampleError::AVariantWithALongDescription;
     assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithALongDescription /* Unit-like variant. A more verbose description */");
-    assert_eq!(&format!("{}", e), "Unit-like variant. A more verbose description");
+    assert_eq!(&format!("{:#}", e), "Unit-like variant. A more verbose description");
     assert_eq!(e.description(), "Unit-like variant");
     assert!(e.cause().is_none());
 
     let e = ExampleError::AVariantWithArgs { flim: 123u32, flam: 456u32 };
     assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithArgs { flim: 123, flam: 456 } /* Variant with args. This is a format string. flim is 123. flam is 456. */");
-    assert_eq!(&format!("{}", e), "Variant with args. This is a format string. flim is 123. flam is 456.");
+    assert_eq!(&format!("{:#}", e), "Variant with args. This is a format string. flim is 123. flam is 456.");
     assert_eq!(e.description(), "Variant with args");
     assert!(e.cause().is_none());
 
     let e = ExampleError::AVariantWithACause { blah: true, cause: io::Error::new(io::ErrorKind::PermissionDenied, "Yo you can't do that!") };
-    assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithACause { blah: true, cause: Error { repr: Custom(Custom { kind: PermissionDenied, error: StringError(\"Yo you can\\\'t do that!\") }) } } /* Variant with a cause. self.cause() would return Some(Yo you can't do that!) */");
-    assert_eq!(&format!("{}", e), "Variant with a cause. self.cause() would return Some(Yo you can't do that!)");
+    assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithACause { blah: true, cause: Custom { kind: PermissionDenied, error: \"Yo you can't do that!\" } } /* Variant with a cause. self.cause() would return Some(Yo you can't do that!). Yo you can't do that! */");
+    assert_eq!(&format!("{:#}", e), "Variant with a cause. self.cause() would return Some(Yo you can't do that!). Yo you can't do that!");
     assert_eq!(e.description(), "Variant with a cause");
-    assert_eq!(e.cause().unwrap().description(), "Yo you can't do that!");
+    assert_eq!(e.cause().unwrap().to_string(), "Yo you can't do that!");
 
     let e = ExampleError::from(io::Error::new(io::ErrorKind::PermissionDenied, "You still can't do that!"));
     match e {
         ExampleError::AVariantWithJustACause { .. } => (),
         _ => panic!("wtf?"),
     };
-    assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithJustACause { blah: Error { repr: Custom(Custom { kind: PermissionDenied, error: StringError(\"You still can\\\'t do that!\") }) } } /* This variant can be made `From` an `io::Error` */");
+    assert_eq!(&format!("{:?}", e), "ExampleError::AVariantWithJustACause { blah: Custom { kind: PermissionDenied, error: \"You still can't do that!\" } } /* This variant can be made `From` an `io::Error`. You still can't do that! */");
     assert_eq!(&format!("{}", e), "This variant can be made `From` an `io::Error`");
     assert_eq!(e.description(), "This variant can be made `From` an `io::Error`");
-    assert_eq!(e.cause().unwrap().description(), "You still can't do that!");
+    assert_eq!(e.cause().unwrap().to_string(), "You still can't do that!");
+}
+
+error_def! {
+    ParseError<'a> {
+        UnexpectedToken { token: &'a str } => "Unexpected token" ("Found `{}`", token),
+    }
+}
+
+error_def! {
+    StorageError<E> where E: std::error::Error {
+        Backend { #[from] cause: E } => "Storage backend failed",
+        Missing => "Not found",
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_generics() {
+    use std::error::Error;
+
+    let input = String::from("let x = ;");
+    let e = ParseError::UnexpectedToken { token: &input[8..9] };
+    assert_eq!(&format!("{:#}", e), "Unexpected token. Found `;`");
+    assert!(e.cause().is_none());
+
+    let e: StorageError<io::Error> = StorageError::from(io::Error::new(io::ErrorKind::NotFound, "disk on fire"));
+    assert_eq!(&format!("{:#}", e), "Storage backend failed. disk on fire");
+    assert_eq!(e.cause().unwrap().to_string(), "disk on fire");
+    assert_eq!(&format!("{:?}", StorageError::<io::Error>::Missing), "StorageError::Missing /* Not found */");
+}
+
+error_def! {
+    /// Errors that can only be constructed within this crate.
+    #[derive(Clone, PartialEq)]
+    #[non_exhaustive]
+    pub(crate) AttributedError {
+        Unit => "A unit variant",
+        WithArgs { code: u32 } => "A variant with args" ("code {}", code),
+    }
+}
+
+#[test]
+fn test_attributes() {
+    let e = AttributedError::WithArgs { code: 3 };
+    assert!(e.clone() == e);
+    assert!(AttributedError::Unit != e);
+    assert_eq!(&format!("{:#}", e), "A variant with args. code 3");
+}
+
+error_def! {
+    VariantAttrsError {
+        /// Only exists when the (never enabled) configuration is set.
+        #[cfg(any())]
+        Disabled(#[from] std::fmt::Error) => "Disabled",
+        #[cfg(all())]
+        Enabled(#[from] io::Error) => "Enabled",
+        #[deprecated]
+        Old => "An old variant",
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_variant_attributes() {
+    let e = VariantAttrsError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    assert_eq!(&format!("{}", e), "Enabled");
+    assert_eq!(&format!("{:?}", VariantAttrsError::Old), "VariantAttrsError::Old /* An old variant */");
+}
+
+error_def! {
+    ConfigError {
+        Read { #[from] cause: io::Error } => "Failed to read config",
+        Write { #[source] cause: io::Error } => "Failed to write config",
+    }
+}
+
+#[test]
+fn test_source_without_from() {
+    use std::error::Error;
+
+    let e = ConfigError::Write { cause: io::Error::new(io::ErrorKind::PermissionDenied, "read-only") };
+    assert_eq!(e.source().unwrap().to_string(), "read-only");
+
+    let e = ConfigError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+    assert!(matches!(e, ConfigError::Read { .. }));
+    assert_eq!(e.source().unwrap().to_string(), "missing");
+}
+
+error_def! {
+    LoadError {
+        Io {
+            #[from] cause: io::Error,
+            #[default] path: Option<std::path::PathBuf>,
+            #[default = 3] retries: u32,
+        } => "Failed to load" ("retried {} times", retries),
+        Parse(#[from] std::num::ParseIntError, #[default = "<unknown>"] &'static str) => "Failed to parse",
+    }
+}
+
+#[test]
+fn test_from_with_defaults() {
+    let e = LoadError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+    match e {
+        LoadError::Io { ref path, retries, .. } => {
+            assert!(path.is_none());
+            assert_eq!(retries, 3);
+        },
+        _ => panic!("wrong variant"),
+    }
+    assert_eq!(&format!("{:#}", e), "Failed to load. retried 3 times. missing");
+
+    let e = LoadError::from("x".parse::<u32>().unwrap_err());
+    assert!(matches!(e, LoadError::Parse(_, "<unknown>")));
+}
+
+error_def! {
+    CaptureError {
+        Range { low: u32, high: u32, value: u32 }
+            => "Out of range" ("{value} is not between {low} and {high:?}"),
+        Padded { name: String, width: usize }
+            => "Padded" ("[{name:>width$}] {{literal}} {0}", width * 2),
+        Explicit { value: u32 }
+            => "Explicit" ("{value} {extra}", extra = value + 1),
+    }
+}
+
+#[test]
+fn test_captured_fields() {
+    let e = CaptureError::Range { low: 1, high: 10, value: 12 };
+    assert_eq!(&format!("{:#}", e), "Out of range. 12 is not between 1 and 10");
+
+    let e = CaptureError::Padded { name: String::from("ab"), width: 4 };
+    assert_eq!(&format!("{:#}", e), "Padded. [  ab] {literal} 8");
+
+    let e = CaptureError::Explicit { value: 4 };
+    assert_eq!(&format!("{:#}", e), "Explicit. 4 5");
+}
+
+error_def! {
+    #[error_def(reference)]
+    DbError {
+        NotFound = "E-DB-0004" => "Record not found",
+        Timeout { seconds: u32 } = "E-DB-0007" => "Timed out" ("after {seconds}s"),
+        Io(#[from] io::Error) = "E-DB-0010" => "I/O error",
+    }
+}
+
+error_def! {
+    ExitError {
+        Usage = 2 => "Bad usage",
+        Config { path: String } = 78 => "Bad config",
+    }
+}
+
+#[test]
+fn test_codes() {
+    assert_eq!(DbError::NotFound.code(), "E-DB-0004");
+    assert_eq!(DbError::Timeout { seconds: 3 }.code(), "E-DB-0007");
+    assert_eq!(DbError::CODES, &["E-DB-0004", "E-DB-0007", "E-DB-0010"]);
+    assert!(matches!(DbError::try_from("E-DB-0004"), Ok(DbError::NotFound)));
+    assert!(matches!(DbError::try_from("E-DB-0007"), Err("E-DB-0007")));
+
+    let code: u32 = ExitError::Config { path: String::new() }.code();
+    assert_eq!(code, 78);
+    assert_eq!(ExitError::CODES, &[2, 78]);
+    assert!(matches!(ExitError::try_from(2), Ok(ExitError::Usage)));
+    assert!(matches!(ExitError::try_from(3), Err(3)));
+}
+
+// Error modules often shadow `Result`, which the generated code mustn't depend on.
+mod result_alias {
+    use error_def::error_def;
+
+    pub type Result<T> = std::result::Result<T, AliasError>;
+
+    error_def! {
+        AliasError {
+            Missing = 1 => "Missing",
+        }
+    }
+
+    pub fn lookup(code: u32) -> Result<()> {
+        Err(AliasError::try_from(code).unwrap_or(AliasError::Missing))
+    }
+}
+
+#[test]
+fn test_result_alias() {
+    assert!(matches!(result_alias::lookup(1), Err(result_alias::AliasError::Missing)));
+}
+
+fn utf8_message(e: std::str::Utf8Error) -> String {
+    format!("invalid byte at {}", e.valid_up_to())
+}
+
+error_def! {
+    ConvertedError {
+        Utf8 { #[from(std::str::Utf8Error, with = utf8_message)] message: String } => "Bad UTF-8" ("{}", message),
+        Boxed(#[from(fmt::Error, with = Box::new)] #[source] Box<fmt::Error>) => "Formatting failed",
+    }
+}
+
+#[test]
+fn test_from_with() {
+    use std::error::Error;
+
+    #[allow(invalid_from_utf8)]
+    let e = ConvertedError::from(std::str::from_utf8(b"ab\xff").unwrap_err());
+    assert_eq!(&format!("{:#}", e), "Bad UTF-8. invalid byte at 2");
+    assert!(e.source().is_none());
+
+    let e = ConvertedError::from(fmt::Error);
+    assert!(e.source().unwrap().is::<Box<fmt::Error>>());
+}
+
+error_def! {
+    #[error_def(reference)]
+    TupleError {
+        Io(#[from] io::Error) => "I/O failed",
+        Range(u32, u32) => "Out of range" ("{} is not below {}", 0, 1),
+        Message(String) => "Failed" ("{} ({} bytes)", 0, _0.len()),
+        #[cfg(any())]
+        Disabled => "Never compiled",
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_tuple_variants() {
+    use std::error::Error;
+
+    let e = TupleError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+    assert_eq!(&format!("{:#}", e), "I/O failed. no such file");
+    assert_eq!(e.cause().unwrap().to_string(), "no such file");
+    let source = e.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+    let e = TupleError::Range(7, 5);
+    assert_eq!(&format!("{:?}", e), "TupleError::Range(7, 5) /* Out of range. 7 is not below 5 */");
+    assert!(e.cause().is_none());
+
+    let e = TupleError::Message(String::from("oops"));
+    assert_eq!(&format!("{:#}", e), "Failed. oops (4 bytes)");
+}
+
+error_def! {
+    #[error_def(exit_code = 70)]
+    CliError {
+        #[exit_code = 64]
+        Usage { message: String } => "Invalid usage" ("{message}"),
+        #[exit_code = 74]
+        Io(#[from] io::Error) => "I/O failed",
+        Internal => "Internal error",
+    }
+}
+
+error_def! {
+    DefaultExitError {
+        #[exit_code = 2]
+        Usage => "Invalid usage",
+        Other => "Something went wrong",
+    }
+}
+
+#[test]
+fn test_exit_codes() {
+    use std::process::{ExitCode, Termination};
+
+    assert_eq!(CliError::Usage { message: String::from("no input") }.exit_code(), 64);
+    assert_eq!(CliError::from(io::Error::new(io::ErrorKind::NotFound, "gone")).exit_code(), 74);
+    assert_eq!(CliError::Internal.exit_code(), 70);
+    assert_eq!(DefaultExitError::Usage.exit_code(), 2);
+    assert_eq!(DefaultExitError::Other.exit_code(), 1);
+
+    assert_eq!(CliError::Internal.report_exit(), ExitCode::from(70));
+    assert_eq!(DefaultExitErrorExit::from(Err(DefaultExitError::Usage)).report(), ExitCode::from(2));
+    assert_eq!(DefaultExitErrorExit(Ok(())).report(), ExitCode::SUCCESS);
+
+    // The error's own `report` isn't hidden by `Termination::report`.
+    assert_eq!(&format!("{}", DefaultExitError::Other.report()), "Error: Something went wrong");
+}
+
+error_def! {
+    #[error_def(status = 400)]
+    pub ApiError {
+        #[status = 404]
+        NotFound { #[extension] resource: String, #[extension] id: u64 }
+            => "Resource not found" ("No {resource} with id {id}"),
+        BadRequest => "Bad request",
+        #[status = 503]
+        Unavailable { retry_after: u32 } => "Service unavailable" ("Try again in \"{retry_after}\" seconds"),
+    }
+}
+
+error_def! {
+    CodedApiError {
+        Conflict = "E-409" => "Conflict",
+        #[status = 401]
+        Unauthorized = "E-401" => "Unauthorized",
+    }
+}
+
+#[test]
+fn test_problem_details() {
+    let e = ApiError::NotFound { resource: String::from("user"), id: 42 };
+    assert_eq!(e.status_code(), 404);
+    assert_eq!(ApiError::BadRequest.status_code(), 400);
+    assert_eq!(CodedApiError::Conflict.status_code(), 500);
+
+    let problem = e.to_problem_details();
+    assert_eq!(problem, ApiErrorProblemDetails {
+        r#type: String::from("NotFound"),
+        title: "Resource not found",
+        status: 404,
+        detail: Some(String::from("No user with id 42")),
+        extensions: vec![("resource", String::from("user")), ("id", String::from("42"))],
+    });
+    assert_eq!(
+        problem.to_json(),
+        r#"{"type":"NotFound","title":"Resource not found","status":404,"detail":"No user with id 42","resource":"user","id":"42"}"#
+    );
+
+    let problem = ApiError::Unavailable { retry_after: 30 }.to_problem_details();
+    assert!(problem.extensions.is_empty());
+    assert_eq!(
+        problem.to_json(),
+        r#"{"type":"Unavailable","title":"Service unavailable","status":503,"detail":"Try again in \"30\" seconds"}"#
+    );
+
+    let problem = CodedApiError::Unauthorized.to_problem_details();
+    assert_eq!(problem.r#type, "E-401");
+    assert_eq!(problem.detail, None);
+    assert_eq!(problem.to_json(), r#"{"type":"E-401","title":"Unauthorized","status":401}"#);
+}
+
+error_def! {
+    #[error_def(catalogs = "examples/locales")]
+    LocalizedError {
+        NotFound { id: u32 } => "Record not found" ("No record with id {id}"),
+        Timeout => "Timed out",
+        Overflow(u8, u8) => "Overflowed" ("{} + {} doesn't fit", 0, 1),
+    }
+}
+
+#[test]
+fn test_localized() {
+    let e = LocalizedError::NotFound { id: 7 };
+    assert_eq!(e.message_key(), "localized-error-not-found");
+    assert_eq!(e.localized("fr"), "Enregistrement introuvable. Aucun enregistrement avec l'identifiant 7 {sic}");
+    assert_eq!(e.localized("fr-CA"), "Enregistrement introuvable. Aucun enregistrement avec l'identifiant 7 {sic}");
+    assert_eq!(e.localized("en"), "Record not found. No record with id 7");
+    assert_eq!(e.localized("de"), "Record not found. No record with id 7");
+
+    // Messages missing from a catalog fall back to the `{:#}` text.
+    assert_eq!(LocalizedError::Timeout.localized("fr"), "Timed out");
+    assert_eq!(LocalizedError::Overflow(200, 100).localized("fr_FR"), "Dépassement. 200 + 100 ne rentre pas");
+}
+
+#[test]
+fn test_reference() {
+    assert_eq!(
+        DbError::REFERENCE_JSON,
+        concat!(
+            r#"{"type":"DbError","variants":["#,
+            r#"{"name":"NotFound","fields":[],"short":"Record not found","long":null,"long_args":[],"code":"E-DB-0004","from":null,"cfg":null},"#,
+            r#"{"name":"Timeout","fields":[{"name":"seconds","type":"u32"}],"short":"Timed out","long":"after {seconds}s","long_args":[],"code":"E-DB-0007","from":null,"cfg":null},"#,
+            r#"{"name":"Io","fields":[{"name":"0","type":"io::Error"}],"short":"I/O error","long":null,"long_args":[],"code":"E-DB-0010","from":"io::Error","cfg":null}"#,
+            r#"]}"#,
+        )
+    );
+
+    assert_eq!(TupleError::REFERENCE_MARKDOWN, "\
+## `TupleError`
+
+### `Io`
+
+I/O failed
+
+- Fields: `0: io::Error`
+- Converted from: `io::Error`
+
+### `Range`
+
+Out of range
+
+- Fields: `0: u32`, `1: u32`
+- Details: `{} is not below {}` with `_0`, `_1`
+
+### `Message`
+
+Failed
+
+- Fields: `0: String`
+- Details: `{} ({} bytes)` with `_0`, `_0.len()`
+
+### `Disabled`
+
+Never compiled
+
+- Only when: `cfg(any())`
+");
+}
+
+error_def! {
+    /// Failed to load the configuration.
+    #[derive(PartialEq)]
+    pub struct ConfigLoadError {
+        pub path: String,
+        pub line: u32,
+    } => "Failed to load config" ("{path}:{line}")
+}
+
+error_def! {
+    struct ReadError {
+        #[default] path: Option<String>,
+        #[from] cause: io::Error,
+    } => "Read failed"
+}
+
+error_def! {
+    pub(crate) struct WrappedError(#[from] fmt::Error) => "Formatting failed"
+}
+
+error_def! {
+    #[error_def(status = 504, reference)]
+    struct TimeoutError = 408 => "Timed out"
+}
+
+#[test]
+fn test_structs() {
+    use std::error::Error;
+
+    let e = ConfigLoadError { path: String::from("app.toml"), line: 3 };
+    assert_eq!(&format!("{:#}", e), "Failed to load config. app.toml:3");
+    assert_eq!(
+        &format!("{:?}", e),
+        r#"ConfigLoadError { path: "app.toml", line: 3 } /* Failed to load config. app.toml:3 */"#
+    );
+    assert!(e.source().is_none());
+    assert!(e == e);
+
+    let e = ReadError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    assert_eq!(&format!("{:#}", e), "Read failed. gone");
+    assert!(e.path.is_none());
+    assert_eq!(e.source().unwrap().to_string(), "gone");
+
+    let e = WrappedError::from(fmt::Error);
+    assert_eq!(&format!("{:?}", e), "WrappedError(Error) /* Formatting failed. an error occurred when formatting an argument */");
+    assert!(e.source().unwrap().is::<fmt::Error>());
+
+    assert_eq!(&format!("{:?}", TimeoutError), "TimeoutError /* Timed out */");
+    assert_eq!(TimeoutError.code(), 408);
+    assert_eq!(TimeoutError.status_code(), 504);
+    assert!(matches!(TimeoutError::try_from(408), Ok(TimeoutError)));
+    assert_eq!(TimeoutError::REFERENCE_MARKDOWN, "## `TimeoutError`\n\nTimed out\n\n- Code: `408`\n");
+}
+
+error_def! {
+    UmbrellaError<E> where E: std::error::Error {
+        #[transparent]
+        Read(#[from] ReadError) => "Read failed",
+        #[transparent]
+        Backend { inner: E } => "Backend failed",
+        Other => "Something else failed",
+    }
+}
+
+error_def! {
+    #[transparent]
+    struct OpaqueError(io::Error) => "Opaque"
+}
+
+#[test]
+fn test_transparent() {
+    use std::error::Error;
+
+    let e: UmbrellaError<fmt::Error> = ReadError::from(io::Error::new(io::ErrorKind::NotFound, "gone")).into();
+    assert_eq!(&format!("{:#}", e), "Read failed. gone");
+    assert_eq!(e.source().unwrap().to_string(), "gone");
+    assert!(e.source().unwrap().is::<io::Error>());
+
+    let e: UmbrellaError<fmt::Error> = UmbrellaError::Backend { inner: fmt::Error };
+    assert_eq!(&format!("{:#}", e), "an error occurred when formatting an argument");
+    assert!(e.source().is_none());
+
+    let e = OpaqueError(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    assert_eq!(&format!("{:#}", e), "gone");
+    assert_eq!(&format!("{:?}", e), r#"OpaqueError(Custom { kind: NotFound, error: "gone" }) /* gone */"#);
+    assert!(e.source().is_none());
+}
+
+error_def! {
+    #[error_def(kind)]
+    pub FetchError<E> {
+        Timeout { seconds: u32 } => "Timed out",
+        Refused => "Connection refused",
+        Backend(E) => "Backend failed",
+    }
+}
+
+#[test]
+fn test_kind() {
+    use std::collections::HashMap;
+
+    let e: FetchError<()> = FetchError::Timeout { seconds: 3 };
+    assert_eq!(e.kind(), FetchErrorKind::Timeout);
+    assert!(e == FetchErrorKind::Timeout);
+    assert!(FetchErrorKind::Refused != e);
+    assert_eq!(FetchErrorKind::ALL, &[FetchErrorKind::Timeout, FetchErrorKind::Refused, FetchErrorKind::Backend]);
+
+    let errors = [FetchError::Backend(()), FetchError::Refused, FetchError::Backend(())];
+    let mut counts: HashMap<FetchErrorKind, u32> = HashMap::new();
+    for e in &errors {
+        *counts.entry(e.kind()).or_default() += 1;
+    }
+    assert_eq!(counts[&FetchErrorKind::Backend], 2);
+}
+
+error_def! {
+    #[error_def(accessors)]
+    pub UploadError<E> {
+        TooLarge { size: u64, limit: u64 } => "File too large",
+        Rejected(String) => "Upload rejected",
+        IOFailed(#[from] E) => "I/O failed",
+        Cancelled => "Upload cancelled",
+    }
+}
+
+error_def! {
+    #[error_def(accessors)]
+    struct QuotaError {
+        user: String,
+        used: u64,
+    } => "Quota exceeded"
+}
+
+#[test]
+fn test_accessors() {
+    let e: UploadError<io::Error> = UploadError::too_large(10u32, 5u8);
+    assert!(e.is_too_large());
+    assert!(!e.is_cancelled());
+    assert_eq!(e.as_too_large(), Some((&10, &5)));
+    assert!(e.as_rejected().is_none());
+    assert!(matches!(e.into_rejected(), Err(UploadError::TooLarge { size: 10, limit: 5 })));
+
+    let e: UploadError<io::Error> = UploadError::rejected("bad name");
+    assert_eq!(e.as_rejected().map(String::as_str), Some("bad name"));
+    assert_eq!(e.into_rejected().unwrap(), "bad name");
+
+    let e: UploadError<io::Error> = UploadError::io_failed(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    assert!(e.is_io_failed());
+    assert_eq!(e.into_io_failed().unwrap().kind(), io::ErrorKind::NotFound);
+    assert!(UploadError::<io::Error>::cancelled().is_cancelled());
+
+    let e = QuotaError::new("alice", 12u32);
+    assert_eq!((e.user.as_str(), e.used), ("alice", 12));
+}
+
+error_def! {
+    #[error_def(context)]
+    pub StoreError {
+        Io { #[from] cause: io::Error, path: String } => "I/O failed" ("while accessing {path}"),
+        Parse { #[source] cause: std::num::ParseIntError, line: u32 } => "Parse failed",
+        Utf8 { #[from(std::str::Utf8Error, with = utf8_message)] message: String } => "Bad UTF-8",
+        Missing { key: String } => "Missing key",
+        Empty => "Store is empty",
+    }
+}
+
+#[test]
+fn test_context() {
+    fn read(path: &str) -> Result<String, StoreError> {
+        let contents = Err(io::Error::new(io::ErrorKind::NotFound, "gone")).context_io(path)?;
+        Ok(contents)
+    }
+
+    match read("/tmp/store") {
+        Err(StoreError::Io { ref path, .. }) => assert_eq!(path, "/tmp/store"),
+        e => panic!("wrong variant: {:?}", e),
+    }
+
+    let e = "x".parse::<u8>().context_parse(3u8).unwrap_err();
+    assert!(matches!(e, StoreError::Parse { line: 3, .. }));
+
+    let bytes = vec![0xff];
+    let e = std::str::from_utf8(&bytes).context_utf8().unwrap_err();
+    assert!(matches!(e, StoreError::Utf8 { ref message } if message == "invalid byte at 0"));
+
+    let e = Err::<(), _>(io::ErrorKind::Other).context_variant(|kind| StoreError::Io { cause: kind.into(), path: String::new() });
+    assert!(matches!(e, Err(StoreError::Io { .. })));
+
+    let values: Vec<u32> = Vec::new();
+    assert!(matches!(values.first().context_empty(), Err(StoreError::Empty)));
+    assert!(matches!(None::<u32>.context_missing("name"), Err(StoreError::Missing { ref key }) if key == "name"));
+    assert_eq!(Some(1).context_empty().unwrap(), 1);
+}
+
+error_def! {
+    QueryError {
+        Failed(#[from] UmbrellaError<fmt::Error>) => "Query failed",
+        Cancelled => "Query cancelled",
+        Rejected { #[source] cause: ReadError, query: String } => "Query rejected" ("{query} is not allowed"),
+    }
+}
+
+error_def! {
+    WrapperError<T> where T: std::error::Error + 'static {
+        Inner { #[from] cause: T } => "Inner failed",
+    }
+}
+
+#[test]
+fn test_chain() {
+    let e = QueryError::from(UmbrellaError::from(ReadError::from(io::Error::new(io::ErrorKind::NotFound, "gone"))));
+    let chain: Vec<String> = e.chain().map(|e| e.to_string()).collect();
+    assert_eq!(chain, ["Query failed", "Read failed", "gone"]);
+    assert!(e.root_cause().is::<io::Error>());
+    assert_eq!(e.find_cause::<io::Error>().unwrap().kind(), io::ErrorKind::NotFound);
+    assert!(e.find_cause::<QueryError>().is_some());
+    assert!(e.find_cause::<fmt::Error>().is_none());
+    assert_eq!(
+        &format!("{}", e.report()),
+        "Error: Query failed\n    Caused by: Read failed\n    Caused by: gone"
+    );
+
+    let e = WrapperError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+    assert_eq!(e.find_cause::<io::Error>().unwrap().kind(), io::ErrorKind::NotFound);
+    assert!(e.root_cause().is::<io::Error>());
+
+    assert_eq!(QueryError::Cancelled.chain().count(), 1);
+    assert!(QueryError::Cancelled.root_cause().is::<QueryError>());
+    assert_eq!(&format!("{}", QueryError::Cancelled.report()), "Error: Query cancelled");
+
+    // The report has the long description but writes the causes on their own lines.
+    let e = QueryError::Rejected {
+        cause: ReadError::from(io::Error::new(io::ErrorKind::NotFound, "gone")),
+        query: String::from("DROP"),
+    };
+    assert_eq!(&format!("{:#}", e), "Query rejected. DROP is not allowed. Read failed. gone");
+    assert_eq!(
+        &format!("{}", e.report()),
+        "Error: Query rejected. DROP is not allowed\n    Caused by: Read failed\n    Caused by: gone"
+    );
+}
+
+error_def! {
+    #[error_def(separator = ": ", punctuation = ".", catalogs = "examples/locales")]
+    SyncError {
+        Conflict { path: String } => "Sync conflict" ("{path} was changed remotely"),
+        Failed(#[from] QueryError) => "Sync failed",
+        Offline => "Offline",
+    }
+}
+
+#[test]
+fn test_verbosity() {
+    let e = SyncError::Conflict { path: String::from("notes.txt") };
+    assert_eq!(&format!("{}", e), "Sync conflict.");
+    assert_eq!(&format!("{:#}", e), "Sync conflict: notes.txt was changed remotely.");
+    assert_eq!(&format!("{:#}", SyncError::Offline), "Offline.");
+    assert_eq!(e.localized("en"), format!("{:#}", e));
+    assert_eq!(e.localized("fr"), "Conflit de synchronisation: notes.txt a été modifié à distance.");
+
+    let e = SyncError::from(QueryError::from(UmbrellaError::from(ReadError::from(io::Error::new(io::ErrorKind::NotFound, "gone")))));
+    assert_eq!(&format!("{}", e), "Sync failed.");
+    assert_eq!(&format!("{:#}", e), "Sync failed: Query failed: Read failed: gone.");
+    assert!(format!("{:?}", e).ends_with(" /* Sync failed: Query failed: Read failed: gone. */"));
+
+    let e = SyncError::Conflict { path: String::from("notes.txt") };
+    assert_eq!(&format!("{}", e.report()), "Error: Sync conflict: notes.txt was changed remotely.");
 }
 
 fn main() {
@@ -0,0 +1,11 @@
+# Messages for the errors in examples/example.rs.
+
+localized-error-not-found = Record not found
+    .long = No record with id { $id }
+localized-error-timeout = Timed out
+localized-error-overflow = Overflowed
+    .long = { $_0 } + { $_1 } doesn't fit
+sync-error-conflict = Sync conflict
+    .long = { $path } was changed remotely
+sync-error-failed = Sync failed
+sync-error-offline = Offline
@@ -0,0 +1,6 @@
+localized-error-not-found = Enregistrement introuvable
+    .long = Aucun enregistrement avec l'identifiant { $id } { "{" }sic{ "}" }
+localized-error-overflow = Dépassement
+    .long = { $_0 } + { $_1 } ne rentre pas
+sync-error-conflict = Conflit de synchronisation
+    .long = { $path } a été modifié à distance
@@ -0,0 +1,117 @@
+#![allow(dead_code)]
+
+use std::fmt;
+use std::io;
+
+use error_def::error_def;
+
+pub struct Handle;
+
+error_def! {
+    #[error_def(serde)]
+    pub RemoteError {
+        NotFound { name: String, id: u64 } => "Not found" ("no such {name}"),
+        Busy => "Server busy",
+        Overflow(u32, i8) => "Overflowed" ("{} + {}", 0, 1),
+    }
+}
+
+error_def! {
+    #[error_def(serde)]
+    pub LocalError {
+        Io { #[from] cause: io::Error, path: String } => "I/O failed",
+        Unserializable { handle: Handle, id: u32 } => "Bad handle",
+    }
+}
+
+// Types without the option are left alone, so they can derive their own impls.
+error_def! {
+    #[derive(serde::Serialize)]
+    pub PlainError {
+        Refused { port: u16 } => "Connection refused",
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Handle")
+    }
+}
+
+#[test]
+fn test_serialize() {
+    let e = RemoteError::NotFound { name: String::from("user"), id: 7 };
+    assert_eq!(
+        serde_json::to_string(&e).unwrap(),
+        r#"{"variant":"NotFound","short":"Not found","message":"Not found. no such user","fields":{"name":"user","id":7},"causes":[]}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&RemoteError::Busy).unwrap(),
+        r#"{"variant":"Busy","short":"Server busy","message":"Server busy","causes":[]}"#
+    );
+
+    let e = LocalError::Io { cause: io::Error::new(io::ErrorKind::NotFound, "gone"), path: String::from("/tmp/x") };
+    assert_eq!(
+        serde_json::to_string(&e).unwrap(),
+        r#"{"variant":"Io","short":"I/O failed","message":"I/O failed. gone","fields":{"path":"/tmp/x"},"causes":["gone"]}"#
+    );
+
+    let e = LocalError::Unserializable { handle: Handle, id: 3 };
+    assert_eq!(
+        serde_json::to_string(&e).unwrap(),
+        r#"{"variant":"Unserializable","short":"Bad handle","message":"Bad handle","fields":{"id":3},"causes":[]}"#
+    );
+}
+
+#[test]
+fn test_opt_in() {
+    assert_eq!(serde_json::to_string(&PlainError::Refused { port: 80 }).unwrap(), r#"{"Refused":{"port":80}}"#);
+}
+
+#[test]
+fn test_deserialize() {
+    let e = RemoteError::NotFound { name: String::from("user"), id: 7 };
+    let json = serde_json::to_string(&e).unwrap();
+    match serde_json::from_str(&json).unwrap() {
+        RemoteError::NotFound { name, id } => assert_eq!((name.as_str(), id), ("user", 7)),
+        e => panic!("wrong variant: {:?}", e),
+    }
+
+    let json = serde_json::to_string(&RemoteError::Overflow(250, -3)).unwrap();
+    assert!(matches!(serde_json::from_str(&json).unwrap(), RemoteError::Overflow(250, -3)));
+
+    // Only the variant and fields are needed, in any order.
+    let e: RemoteError = serde_json::from_str(r#"{"fields":{"0":1,"1":2},"variant":"Overflow"}"#).unwrap();
+    assert!(matches!(e, RemoteError::Overflow(1, 2)));
+    assert!(matches!(serde_json::from_str(r#"{"variant":"Busy"}"#).unwrap(), RemoteError::Busy));
+    assert!(serde_json::from_str::<RemoteError>(r#"{"variant":"Missing"}"#).is_err());
+}
+
+// Error modules often shadow `Result`, which the generated code mustn't depend on.
+mod result_alias {
+    use error_def::error_def;
+
+    pub type Result<T> = std::result::Result<T, AliasError>;
+
+    error_def! {
+        #[error_def(serde)]
+        AliasError {
+            Missing { key: String } => "Missing",
+            Overflow(u8) => "Overflow",
+        }
+    }
+
+    pub fn parse(json: &str) -> Result<AliasError> {
+        Ok(serde_json::from_str(json).unwrap())
+    }
+}
+
+#[test]
+fn test_result_alias() {
+    let e = result_alias::AliasError::Missing { key: String::from("name") };
+    let json = serde_json::to_string(&e).unwrap();
+    assert!(matches!(result_alias::parse(&json), Ok(result_alias::AliasError::Missing { ref key }) if key == "name"));
+}
+
+fn main() {
+}
@@ -0,0 +1,221 @@
+//! Constructors, predicates and accessors for each variant, generated with
+//! `#[error_def(accessors)]`.
+//!
+//! For a variant `NotFound { name: String, id: u32 }` this generates `not_found(name, id)`, which
+//! takes `impl Into<T>` arguments, `is_not_found()`, `as_not_found()`, which returns references
+//! to the fields, and `into_not_found()`, which returns the fields or the error itself if it is a
+//! different variant. A struct only gets a constructor, which is named `new`.
+
+use std::collections::HashMap;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Fields, Ident};
+
+use crate::{field_bindings, forwarded_attrs, ErrorDef, Errors, TypeKind, VariantDef};
+
+/// Methods which error_def can generate, which a variant's constructor must not be named after.
+const GENERATED_METHODS: &[&str] = &[
+    "chain",
+    "code",
+    "exit_code",
+    "find_cause",
+    "fmt_details",
+    "kind",
+    "localized",
+    "message_key",
+    "report",
+    "report_exit",
+    "root_cause",
+    "status_code",
+    "to_problem_details",
+];
+
+pub fn expand_accessors(def: &ErrorDef) -> syn::Result<TokenStream> {
+    let ErrorDef {
+        ref options,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+    if !options.accessors {
+        return Ok(TokenStream::new());
+    }
+
+    let mut errors = Errors::new();
+    let mut methods = Vec::new();
+    let mut generated: HashMap<String, &Ident> = HashMap::new();
+    for v in variants {
+        let VariantDef { ref variant, .. } = *v;
+        let name = &variant.ident;
+        let path = def.variant_path(variant);
+        let snake_name = match def.kind {
+            TypeKind::Enum => snake_case(name),
+            TypeKind::Struct => String::from("new"),
+        };
+        if GENERATED_METHODS.contains(&snake_name.as_str()) {
+            errors.push(syn::Error::new(
+                name.span(),
+                format!("The constructor for `{}` would conflict with the generated `{}` method", name, snake_name),
+            ));
+            continue;
+        }
+
+        // Different variants, such as `IOError` and `IoError`, can have the same snake_case name.
+        let mut method_names = vec![snake_name.clone()];
+        if def.kind == TypeKind::Enum {
+            method_names.push(format!("is_{}", snake_name));
+            if !variant.fields.is_empty() {
+                method_names.push(format!("as_{}", snake_name));
+                method_names.push(format!("into_{}", snake_name));
+            }
+        }
+        let conflict = method_names.into_iter().find_map(|method_name| {
+            let other = *generated.entry(method_name.clone()).or_insert(name);
+            (other != name).then_some((method_name, other))
+        });
+        if let Some((method_name, other)) = conflict {
+            errors.push(syn::Error::new(
+                name.span(),
+                format!("The `{}` method for `{}` would conflict with the one for `{}`", method_name, name, other),
+            ));
+            continue;
+        }
+
+        // Uses of a deprecated variant's methods should be warned about in the same way.
+        let forwarded_attrs = forwarded_attrs(variant);
+        let deprecated = variant.attrs.iter().filter(|attr| attr.path().is_ident("deprecated"));
+        let attrs = quote!(#forwarded_attrs #(#deprecated)*);
+
+        let bindings = field_bindings(&variant.fields);
+        let types: Vec<_> = variant.fields.iter().map(|field| &field.ty).collect();
+        let value = match variant.fields {
+            Fields::Unit => quote!(#path),
+            Fields::Named(..) => quote!(#path { #(#bindings: #bindings.into(),)* }),
+            Fields::Unnamed(..) => quote!(#path(#(#bindings.into(),)*)),
+        };
+        let constructor = match ident(&snake_name, name.span()) {
+            Ok(constructor) => constructor,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            },
+        };
+        let constructor_doc = format!(" Create a [`{}`] error.", path.to_string().replace(' ', ""));
+        methods.push(quote! {
+            #[doc = #constructor_doc]
+            #attrs
+            pub fn #constructor(#(#bindings: impl ::std::convert::Into<#types>,)*) -> Self {
+                #value
+            }
+        });
+        if def.kind == TypeKind::Struct {
+            continue;
+        }
+
+        let pattern = def.variant_pattern(variant);
+        let is_fn = ident(&format!("is_{}", snake_name), name.span())?;
+        let is_doc = format!(" Whether this is a [`{}`] error.", path.to_string().replace(' ', ""));
+        methods.push(quote! {
+            #[doc = #is_doc]
+            #attrs
+            pub fn #is_fn(&self) -> bool {
+                #[allow(unused)]
+                match *self {
+                    #pattern => true,
+                    _ => false,
+                }
+            }
+        });
+        if variant.fields.is_empty() {
+            continue;
+        }
+
+        // A single field is returned by itself rather than in a tuple.
+        let (ref_type, owned_type, fields) = match *types {
+            [ty] => {
+                let binding = &bindings[0];
+                (quote!(&#ty), quote!(#ty), quote!(#binding))
+            },
+            _ => (quote!((#(&#types,)*)), quote!((#(#types,)*)), quote!((#(#bindings,)*))),
+        };
+        let owned_pattern = match variant.fields {
+            Fields::Named(..) => quote!(#path { #(#bindings,)* }),
+            Fields::Unnamed(..) => quote!(#path(#(#bindings,)*)),
+            Fields::Unit => unreachable!(),
+        };
+        let as_fn = ident(&format!("as_{}", snake_name), name.span())?;
+        let as_doc = format!(
+            " The fields of this error if it is a [`{}`] error.",
+            path.to_string().replace(' ', ""),
+        );
+        let into_fn = ident(&format!("into_{}", snake_name), name.span())?;
+        let into_doc = format!(
+            " The fields of this error if it is a [`{}`] error, or otherwise the error itself.",
+            path.to_string().replace(' ', ""),
+        );
+        methods.push(quote! {
+            #[doc = #as_doc]
+            #attrs
+            pub fn #as_fn(&self) -> ::std::option::Option<#ref_type> {
+                match *self {
+                    #pattern => Some(#fields),
+                    _ => None,
+                }
+            }
+
+            #[doc = #into_doc]
+            #attrs
+            pub fn #into_fn(self) -> ::std::result::Result<#owned_type, Self> {
+                match self {
+                    #owned_pattern => Ok(#fields),
+                    #[allow(unreachable_patterns)]
+                    other => Err(other),
+                }
+            }
+        });
+    }
+    errors.finish()?;
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            #(#methods)*
+        }
+    })
+}
+
+/// Convert a variant name such as `AVariantWithArgs` or `IOError` to snake case, such as
+/// `a_variant_with_args` or `io_error`.
+pub fn snake_case(name: &Ident) -> String {
+    let name = name.to_string();
+    let chars: Vec<char> = name.trim_start_matches("r#").chars().collect();
+    let mut snake = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if prev != '_' && (prev.is_lowercase() || prev.is_numeric() || next_is_lower) {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// An identifier for a generated method, which is a raw identifier if the name is a keyword. The
+/// keywords which can't be raw identifiers are reported as errors at `span`.
+pub fn ident(name: &str, span: Span) -> syn::Result<Ident> {
+    if ["crate", "self", "super", "Self"].contains(&name) {
+        return Err(syn::Error::new(
+            span,
+            format!("A method named `{}` can't be generated, as `{}` is a reserved keyword", name, name),
+        ));
+    }
+    match syn::parse_str::<Ident>(name) {
+        Ok(ident) => Ok(ident),
+        Err(..) => Ok(format_ident!("r#{}", name)),
+    }
+}
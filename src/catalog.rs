@@ -0,0 +1,395 @@
+//! Localized messages, read at compile time from the catalogs given by
+//! `#[error_def(catalogs = "...")]`.
+//!
+//! A catalog is a file named after its locale, such as `fr.ftl`, written in a subset of the
+//! Fluent syntax. Each variant's message is keyed by the kebab-case type and variant names, or
+//! just the type name for a struct. The message's value is the short description and its `.long`
+//! attribute is the long description.
+//! Fields are referred to as `{ $name }`, or `{ $_0 }` in tuple variants.
+//!
+//! ```ftl
+//! # fr.ftl
+//! db-error-not-found = Enregistrement introuvable
+//!     .long = Aucun enregistrement avec l'identifiant { $id }
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Ident, LitStr};
+
+use crate::{field_bindings, forwarded_attrs, ErrorDef, Errors, TypeKind};
+
+/// A piece of a message: either literal text or the name of a field.
+enum Segment {
+    Text(String),
+    Field(String),
+}
+
+struct Message {
+    short: Vec<Segment>,
+    long: Option<Vec<Segment>>,
+}
+
+struct Catalog {
+    locale: String,
+    path: PathBuf,
+    messages: HashMap<String, Message>,
+}
+
+/// Generate the `message_key` and `localized` methods for a type with catalogs.
+pub fn expand_localized(def: &ErrorDef) -> syn::Result<TokenStream> {
+    let ErrorDef {
+        ref options,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+    let dir = match options.catalogs {
+        Some(ref dir) => dir,
+        None => return Ok(TokenStream::new()),
+    };
+
+    // Messages are joined and punctuated in the same way as by `{:#}`.
+    let separator = Segment::Text(match options.separator {
+        Some(ref separator) => separator.value(),
+        None => String::from(". "),
+    });
+    let punctuation = options.punctuation.as_ref().map(|punctuation| Segment::Text(punctuation.value()));
+
+    let catalogs = read_catalogs(dir)?;
+    let default_locale = match options.default_locale {
+        Some(ref locale) => locale.value(),
+        None => String::from("en"),
+    };
+    let default_catalog = match catalogs.iter().find(|catalog| catalog.locale == default_locale) {
+        Some(catalog) => catalog,
+        None => {
+            return Err(syn::Error::new(
+                dir.span(),
+                format!("There is no catalog for the default locale `{}`", default_locale),
+            ));
+        },
+    };
+
+    let mut errors = Errors::new();
+    let mut key_impl_arms = Vec::new();
+    let mut localized_impl_arms = Vec::new();
+    for v in variants {
+        let variant = &v.variant;
+        let name = &variant.ident;
+        let key = match def.kind {
+            TypeKind::Enum => message_key(&[type_name, name]),
+            TypeKind::Struct => message_key(&[type_name]),
+        };
+        if !default_catalog.messages.contains_key(&key) {
+            errors.push(syn::Error::new(
+                name.span(),
+                format!("The default catalog `{}` has no message `{}`", default_catalog.path.display(), key),
+            ));
+        }
+
+        let bindings = field_bindings(&variant.fields);
+        let mut locale_arms = Vec::new();
+        for catalog in &catalogs {
+            let message = match catalog.messages.get(&key) {
+                Some(message) => message,
+                None => continue,
+            };
+            let mut format_str = String::new();
+            let mut format_args: Vec<&Ident> = Vec::new();
+            let mut segments: Vec<&Segment> = message.short.iter().collect();
+            if let Some(ref long) = message.long {
+                segments.push(&separator);
+                segments.extend(long);
+            }
+            segments.extend(&punctuation);
+            for segment in segments {
+                match *segment {
+                    Segment::Text(ref text) => format_str.push_str(&text.replace('{', "{{").replace('}', "}}")),
+                    Segment::Field(ref field) => match bindings.iter().find(|binding| *binding == field) {
+                        Some(binding) => {
+                            format_str.push_str(&format!("{{{}}}", field));
+                            if !format_args.contains(&binding) {
+                                format_args.push(binding);
+                            }
+                        },
+                        None => {
+                            errors.push(syn::Error::new(
+                                dir.span(),
+                                format!(
+                                    "Message `{}` in `{}` refers to `${}`, which is not a field of `{}`",
+                                    key,
+                                    catalog.path.display(),
+                                    field,
+                                    name,
+                                ),
+                            ));
+                        },
+                    },
+                }
+            }
+            let locale = &catalog.locale;
+            locale_arms.push(quote! {
+                #locale => format!(#format_str, #(#format_args = #format_args,)*),
+            });
+        }
+
+        let path = def.variant_path(variant);
+        let forwarded_attrs = forwarded_attrs(variant);
+        key_impl_arms.push(quote! {
+            #forwarded_attrs
+            #path { .. } => #key,
+        });
+        let pattern = def.variant_pattern(variant);
+        localized_impl_arms.push(quote! {
+            #forwarded_attrs
+            #pattern => match locale {
+                #(#locale_arms)*
+                _ => format!("{:#}", self),
+            },
+        });
+    }
+    errors.finish()?;
+
+    // Rebuild when a catalog changes.
+    let paths = catalogs.iter().map(|catalog| catalog.path.to_string_lossy().into_owned());
+    let locales = catalogs.iter().map(|catalog| &catalog.locale);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        #(const _: &[u8] = include_bytes!(#paths);)*
+
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            /// The key of this error's message in the message catalogs.
+            pub fn message_key(&self) -> &'static str {
+                match *self {
+                    #(#key_impl_arms)*
+                }
+            }
+
+            /// Render this error in the given locale, such as `"fr"` or `"fr-CA"`. The full
+            /// `{:#}` `Display` text is returned if there is no catalog for the locale or the
+            /// catalog has no message for this error.
+            pub fn localized(&self, locale: &str) -> ::std::string::String
+            where
+                Self: ::std::fmt::Display,
+            {
+                const LOCALES: &[&str] = &[#(#locales,)*];
+                let language = locale.split(['-', '_']).next().unwrap_or(locale);
+                let locale = LOCALES
+                    .iter()
+                    .find(|l| **l == locale)
+                    .or_else(|| LOCALES.iter().find(|l| **l == language))
+                    .copied()
+                    .unwrap_or("");
+                #[allow(unused)]
+                match *self {
+                    #(#localized_impl_arms)*
+                }
+            }
+        }
+    })
+}
+
+/// The key of a variant's message, such as `db-error-not-found` for `DbError::NotFound`, made out
+/// of the names in its path.
+fn message_key(path: &[&Ident]) -> String {
+    let mut key = String::new();
+    for name in path {
+        if !key.is_empty() {
+            key.push('-');
+        }
+        let name = name.to_string();
+        for c in name.trim_start_matches("r#").chars() {
+            let starts_word = c == '_' || c.is_uppercase();
+            if starts_word && !key.is_empty() && !key.ends_with('-') {
+                key.push('-');
+            }
+            if c != '_' {
+                key.extend(c.to_lowercase());
+            }
+        }
+    }
+    key
+}
+
+/// Read every `.ftl` file in the directory `dir`, relative to the crate's root.
+fn read_catalogs(dir: &LitStr) -> syn::Result<Vec<Catalog>> {
+    let error = |message: String| syn::Error::new(dir.span(), message);
+    let root = env::var_os("CARGO_MANIFEST_DIR").ok_or_else(|| error(String::from("CARGO_MANIFEST_DIR is not set")))?;
+    let dir_path = Path::new(&root).join(dir.value());
+    let entries = fs::read_dir(&dir_path)
+        .map_err(|e| error(format!("Failed to read catalog directory `{}`: {}", dir_path.display(), e)))?;
+
+    let mut catalogs = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| error(format!("Failed to read `{}`: {}", dir_path.display(), e)))?.path();
+        if path.extension().is_none_or(|ext| ext != "ftl") {
+            continue;
+        }
+        let locale = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(locale) => String::from(locale),
+            None => continue,
+        };
+        let source = fs::read_to_string(&path).map_err(|e| error(format!("Failed to read `{}`: {}", path.display(), e)))?;
+        let messages = parse_catalog(&source)
+            .map_err(|(line, message)| error(format!("{}:{}: {}", path.display(), line, message)))?;
+        catalogs.push(Catalog { locale, path, messages });
+    }
+    catalogs.sort_by(|a, b| a.locale.cmp(&b.locale));
+    Ok(catalogs)
+}
+
+/// Parse a catalog's messages. Errors are returned along with their line number.
+fn parse_catalog(source: &str) -> Result<HashMap<String, Message>, (usize, String)> {
+    // Each entry is a message's value or one of its attributes, along with the line it started on.
+    struct Entry {
+        key: String,
+        attribute: Option<String>,
+        value: String,
+        line: usize,
+    }
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            let (key, value) = split_entry(trimmed).ok_or((line_number, String::from("Expected `key = value`")))?;
+            if !is_identifier(key) {
+                return Err((line_number, format!("`{}` is not a valid message key", key)));
+            }
+            if entries.iter().any(|entry| entry.key == key) {
+                return Err((line_number, format!("Duplicate message `{}`", key)));
+            }
+            entries.push(Entry {
+                key: String::from(key),
+                attribute: None,
+                value: String::from(value),
+                line: line_number,
+            });
+            continue;
+        }
+
+        let message_key = match entries.last() {
+            Some(entry) => entry.key.clone(),
+            None => return Err((line_number, String::from("Indented line outside of a message"))),
+        };
+        match trimmed.strip_prefix('.') {
+            Some(attribute) => {
+                let (name, value) = split_entry(attribute).ok_or((line_number, String::from("Expected `.long = value`")))?;
+                if name != "long" {
+                    return Err((line_number, format!("Unknown attribute `.{}`; only `.long` is supported", name)));
+                }
+                entries.push(Entry {
+                    key: message_key,
+                    attribute: Some(String::from(name)),
+                    value: String::from(value),
+                    line: line_number,
+                });
+            },
+            None => {
+                // A continuation of the previous value, which keeps its line break.
+                let entry = entries.last_mut().unwrap();
+                if !entry.value.is_empty() {
+                    entry.value.push('\n');
+                }
+                entry.value.push_str(trimmed);
+            },
+        }
+    }
+
+    let mut messages: HashMap<String, Message> = HashMap::new();
+    for entry in entries {
+        let pattern = parse_pattern(&entry.value).map_err(|message| (entry.line, message))?;
+        match entry.attribute {
+            None => {
+                if pattern.is_empty() {
+                    return Err((entry.line, format!("Message `{}` has no value", entry.key)));
+                }
+                messages.insert(entry.key, Message { short: pattern, long: None });
+            },
+            Some(..) => {
+                let message = messages.get_mut(&entry.key).unwrap();
+                if message.long.replace(pattern).is_some() {
+                    return Err((entry.line, format!("Duplicate `.long` attribute on `{}`", entry.key)));
+                }
+            },
+        }
+    }
+    Ok(messages)
+}
+
+/// Split `name = value` into its trimmed name and value.
+fn split_entry(line: &str) -> Option<(&str, &str)> {
+    let (name, value) = line.split_once('=')?;
+    Some((name.trim(), value.trim()))
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Split a message into text and `{ $field }` placeables. String literal placeables such as
+/// `{ "{" }` are treated as text.
+fn parse_pattern(value: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => (),
+            '}' => return Err(String::from("Unmatched `}`")),
+            c => {
+                text.push(c);
+                continue;
+            },
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\')) => text.push(c),
+                        _ => return Err(String::from("Unsupported escape sequence in string literal")),
+                    },
+                    Some(c) => text.push(c),
+                    None => return Err(String::from("Unclosed string literal")),
+                }
+            }
+        } else if chars.next_if_eq(&'$').is_some() {
+            let mut field = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '-') {
+                field.push(c);
+            }
+            if !is_identifier(&field) {
+                return Err(format!("`${}` is not a valid variable name", field));
+            }
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(Segment::Field(field));
+        } else {
+            return Err(String::from("Only `{ $field }` and `{ \"literal\" }` placeables are supported"));
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next() != Some('}') {
+            return Err(String::from("Expected `}` at the end of a placeable"));
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
@@ -0,0 +1,221 @@
+//! Extension traits for `Result` and `Option`, generated with `#[error_def(context)]`, which lift
+//! an error or a `None` into a particular variant while supplying the variant's other fields.
+//!
+//! For a variant `Io { #[from] cause: io::Error, path: String }` the `ResultExt` trait gets
+//! `context_io(path)`, which can be called on any `Result<T, E>` where `E: Into<io::Error>`. A
+//! `#[from(Type, with = function)]` member takes an error which converts into `Type` and passes it
+//! to the function. For a variant without a source, such as `NotFound { name: String }`, the
+//! `OptionExt` trait gets `context_not_found(name)`, which turns a `None` into that variant.
+
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Fields, GenericParam, Generics, Ident};
+
+use crate::accessors::{ident, snake_case};
+use crate::{field_bindings, forwarded_attrs, ErrorDef, Errors, FromWith, VariantDef};
+
+pub fn expand_context(def: &ErrorDef) -> syn::Result<TokenStream> {
+    let ErrorDef {
+        ref options,
+        ref vis,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+    if !options.context {
+        return Ok(TokenStream::new());
+    }
+
+    let mut errors = Errors::new();
+    let mut result_decls = Vec::new();
+    let mut result_impls = Vec::new();
+    let mut option_decls = Vec::new();
+    let mut option_impls = Vec::new();
+    let mut result_methods: HashMap<String, &Ident> = HashMap::new();
+    let mut option_methods: HashMap<String, &Ident> = HashMap::new();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    for v in variants {
+        let VariantDef {
+            ref variant,
+            ref from_idx,
+            ref from_with,
+            ref source_idx,
+            ..
+        } = *v;
+        // A struct's only variant is named after the struct, so its method is `context_<struct>`.
+        let name = &variant.ident;
+        let snake_name = snake_case(name);
+        let cause_idx = source_idx.or(*from_idx);
+        if cause_idx.is_some() && snake_name == "variant" {
+            errors.push(syn::Error::new(
+                name.span(),
+                "The context method for `Variant` would conflict with the generated `context_variant` method",
+            ));
+            continue;
+        }
+        // Methods only conflict with others in the same trait.
+        let generated = if cause_idx.is_some() {
+            &mut result_methods
+        } else {
+            &mut option_methods
+        };
+        if let Some(other) = generated.insert(snake_name.clone(), name) {
+            errors.push(syn::Error::new(
+                name.span(),
+                format!(
+                    "The `context_{}` method for `{}` would conflict with the one for `{}`",
+                    snake_name, name, other,
+                ),
+            ));
+            continue;
+        }
+        let method = ident(&format!("context_{}", snake_name), name.span())?;
+        let path = def.variant_path(variant);
+        let path_str = path.to_string().replace(' ', "");
+
+        let forwarded_attrs = forwarded_attrs(variant);
+        let deprecated: Vec<_> = variant.attrs.iter().filter(|attr| attr.path().is_ident("deprecated")).collect();
+        let bindings = field_bindings(&variant.fields);
+
+        // The error being lifted takes the place of the source, and every other field is an
+        // argument.
+        let mut args = Vec::new();
+        let mut values = Vec::new();
+        for (i, (member, binding)) in variant.fields.iter().zip(&bindings).enumerate() {
+            if Some(i) == cause_idx {
+                match *from_with {
+                    Some(FromWith { ref function, .. }) if Some(i) == *from_idx => {
+                        values.push(quote!((#function)(e.into())));
+                    },
+                    _ => values.push(quote!(e.into())),
+                }
+            } else {
+                let ty = &member.ty;
+                args.push(quote!(#binding: impl ::std::convert::Into<#ty>));
+                values.push(quote!(#binding.into()));
+            }
+        }
+        let value = match variant.fields {
+            Fields::Unit => quote!(#path),
+            Fields::Named(..) => quote!(#path { #(#bindings: #values,)* }),
+            Fields::Unnamed(..) => quote!(#path(#(#values,)*)),
+        };
+
+        match cause_idx {
+            Some(idx) => {
+                // A `#[from(Type, with = function)]` member is built from the converted type.
+                let source_ty = match *from_idx {
+                    Some(from_idx) if from_idx == idx => v.converted_type(idx),
+                    _ => &variant.fields.iter().nth(idx).unwrap().ty,
+                };
+                let doc = format!(" Lift the error into a [`{}`] error.", path_str);
+                result_decls.push(quote! {
+                    #[doc = #doc]
+                    #forwarded_attrs
+                    #(#deprecated)*
+                    fn #method(self, #(#args,)*) -> ::std::result::Result<__T, #type_name #ty_generics>
+                    where
+                        __E: ::std::convert::Into<#source_ty>;
+                });
+                result_impls.push(quote! {
+                    #forwarded_attrs
+                    fn #method(self, #(#args,)*) -> ::std::result::Result<__T, #type_name #ty_generics>
+                    where
+                        __E: ::std::convert::Into<#source_ty>,
+                    {
+                        self.map_err(|e| #value)
+                    }
+                });
+            },
+            None => {
+                let doc = format!(" Turn a `None` into a [`{}`] error.", path_str);
+                option_decls.push(quote! {
+                    #[doc = #doc]
+                    #forwarded_attrs
+                    #(#deprecated)*
+                    fn #method(self, #(#args,)*) -> ::std::result::Result<__T, #type_name #ty_generics>;
+                });
+                option_impls.push(quote! {
+                    #forwarded_attrs
+                    fn #method(self, #(#args,)*) -> ::std::result::Result<__T, #type_name #ty_generics> {
+                        self.ok_or_else(|| #value)
+                    }
+                });
+            },
+        }
+    }
+    errors.finish()?;
+
+    let mut items = Vec::new();
+
+    // The generic `context_variant` method is always present, so the `Result` trait is always
+    // generated.
+    let result_trait = format_ident!("{}ResultExt", type_name);
+    let result_doc = format!(" Methods for lifting the error of a `Result` into a [`{}`].", type_name);
+    let result_generics = trait_generics(generics, &[parse_quote!(__T), parse_quote!(__E)]);
+    let (impl_generics, trait_ty_generics, where_clause) = result_generics.split_for_impl();
+    items.push(quote! {
+        #[doc = #result_doc]
+        #vis trait #result_trait #impl_generics #where_clause {
+            /// Lift the error using `f`, which is given the error and builds the variant.
+            fn context_variant<__F>(self, f: __F) -> ::std::result::Result<__T, #type_name #ty_generics>
+            where
+                __F: ::std::ops::FnOnce(__E) -> #type_name #ty_generics;
+
+            #(#result_decls)*
+        }
+
+        impl #impl_generics #result_trait #trait_ty_generics for ::std::result::Result<__T, __E> #where_clause {
+            fn context_variant<__F>(self, f: __F) -> ::std::result::Result<__T, #type_name #ty_generics>
+            where
+                __F: ::std::ops::FnOnce(__E) -> #type_name #ty_generics,
+            {
+                self.map_err(f)
+            }
+
+            #(#result_impls)*
+        }
+    });
+
+    if !option_decls.is_empty() {
+        let option_trait = format_ident!("{}OptionExt", type_name);
+        let option_doc = format!(" Methods for turning a `None` into a [`{}`].", type_name);
+        let option_generics = trait_generics(generics, &[parse_quote!(__T)]);
+        let (impl_generics, trait_ty_generics, where_clause) = option_generics.split_for_impl();
+        items.push(quote! {
+            #[doc = #option_doc]
+            #vis trait #option_trait #impl_generics #where_clause {
+                #(#option_decls)*
+            }
+
+            impl #impl_generics #option_trait #trait_ty_generics for ::std::option::Option<__T> #where_clause {
+                #(#option_impls)*
+            }
+        });
+    }
+
+    Ok(quote!(#(#items)*))
+}
+
+/// The type's generics, without defaults, followed by `extra`.
+fn trait_generics(generics: &Generics, extra: &[GenericParam]) -> Generics {
+    let mut generics = generics.clone();
+    for param in &mut generics.params {
+        match *param {
+            GenericParam::Type(ref mut param) => {
+                param.eq_token = None;
+                param.default = None;
+            },
+            GenericParam::Const(ref mut param) => {
+                param.eq_token = None;
+                param.default = None;
+            },
+            GenericParam::Lifetime(..) => (),
+        }
+    }
+    generics.params.extend(extra.iter().cloned());
+    generics
+}
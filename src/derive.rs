@@ -0,0 +1,132 @@
+//! Support for `#[derive(ErrorDef)]`. This reads the same information that the `error_def!`
+//! grammar provides out of attributes on a hand-written enum and produces an `ErrorDef` which
+//! can be passed to `expand_impls`.
+
+use syn::{parenthesized, Data, DeriveInput, Lit, LitStr, Variant};
+
+use crate::{check_codes, check_from_impls, check_transparent, parse_code, parse_exit_code, parse_status,
+            take_int_attr, take_transparent, ErrorDef, Errors, LongDescription, MemberMarkers, TypeKind,
+            TypeOptions, VariantDef};
+
+pub fn parse_derive_input(input: DeriveInput) -> syn::Result<ErrorDef> {
+    let DeriveInput {
+        mut attrs,
+        vis,
+        ident: type_name,
+        generics,
+        data,
+        ..
+    } = input;
+
+    let options = TypeOptions::take_from(&mut attrs)?;
+
+    let data = match data {
+        Data::Enum(data) => data,
+        Data::Struct(data) => {
+            return Err(syn::Error::new(data.struct_token.span, "ErrorDef can only be derived for enums"));
+        },
+        Data::Union(data) => {
+            return Err(syn::Error::new(data.union_token.span, "ErrorDef can only be derived for enums"));
+        },
+    };
+
+    // Report errors from all of the variants, not just the first bad one.
+    let mut variants: Vec<VariantDef> = Vec::new();
+    let mut errors = Errors::new();
+    for variant in data.variants {
+        match parse_variant(variant) {
+            Ok(variant) => variants.push(variant),
+            Err(e) => errors.push(e),
+        }
+    }
+    check_from_impls(&variants, &mut errors);
+    check_codes(&variants, &mut errors);
+    check_transparent(&variants, &mut errors);
+    errors.finish()?;
+
+    Ok(ErrorDef {
+        options,
+        kind: TypeKind::Enum,
+        attrs: Vec::new(),
+        vis,
+        type_name,
+        generics,
+        variants,
+    })
+}
+
+fn parse_variant(mut variant: Variant) -> syn::Result<VariantDef> {
+    // Parse the #[error_def(short = "...", long(...), code = ...)] attribute.
+    let mut short_description: Option<LitStr> = None;
+    let mut long_description: Option<LongDescription> = None;
+    let mut code: Option<Lit> = None;
+    for attr in &variant.attrs {
+        if !attr.path().is_ident("error_def") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("short") {
+                if short_description.is_some() {
+                    return Err(meta.error("Duplicate short description"));
+                }
+                short_description = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("long") {
+                if long_description.is_some() {
+                    return Err(meta.error("Duplicate long description"));
+                }
+                let content;
+                parenthesized!(content in meta.input);
+                let mut long: LongDescription = content.parse()?;
+                long.bind_fields(&variant.fields)?;
+                long_description = Some(long);
+                Ok(())
+            } else if meta.path.is_ident("code") {
+                if code.is_some() {
+                    return Err(meta.error("Duplicate error code"));
+                }
+                code = Some(parse_code(meta.value()?)?);
+                Ok(())
+            } else {
+                Err(meta.error("Expected `short`, `long` or `code`"))
+            }
+        })?;
+    }
+    let short_description = match short_description {
+        Some(short_description) => short_description,
+        None => {
+            return Err(syn::Error::new(
+                variant.ident.span(),
+                "Variant is missing an #[error_def(short = \"...\")] attribute",
+            ));
+        },
+    };
+
+    let exit_code = take_int_attr(&mut variant.attrs, "exit_code", parse_exit_code)?;
+    let status = take_int_attr(&mut variant.attrs, "status", parse_status)?;
+    let transparent = take_transparent(&mut variant.attrs)?;
+
+    // Find the members marked #[from], #[source], #[default] or #[extension], if any.
+    let MemberMarkers {
+        from_idx,
+        from_with,
+        source_idx,
+        defaults,
+        extensions,
+    } = MemberMarkers::take_from(&mut variant.fields)?;
+
+    Ok(VariantDef {
+        variant,
+        short_description,
+        from_idx,
+        from_with,
+        source_idx,
+        defaults,
+        code,
+        exit_code,
+        status,
+        extensions,
+        transparent,
+        long_description,
+    })
+}
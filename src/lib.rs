@@ -1,541 +1,1908 @@
-#![feature(quote)]
-#![feature(plugin_registrar)]
-#![feature(rustc_private)]
-#![allow(unused_imports)]
-
-extern crate syntax;
-extern crate rustc_plugin;
-
-use syntax::codemap::{Span, DUMMY_SP, dummy_spanned};
-use syntax::tokenstream::TokenTree;
-use syntax::ext::base::{ExtCtxt, MacResult, DummyResult, SyntaxExtension, MacEager};
-use syntax::parse::token::{Token, DelimToken};
-use syntax::symbol::Symbol;
-use syntax::parse;
-use syntax::util::small_vector::SmallVector;
-use syntax::ast::{self, Variant_, Visibility, VariantData, Variant, LitKind, StructField, Name,
-                  Expr, DUMMY_NODE_ID};
-use syntax::ptr::P;
-use syntax::attr::{mk_sugared_doc_attr, mk_attr_id};
-use syntax::ext::quote::rt::ToTokens;
-
-use rustc_plugin::Registry;
+extern crate proc_macro;
+
+mod accessors;
+mod catalog;
+mod context;
+mod derive;
+mod reference;
+#[cfg(feature = "serde")]
+mod serde;
+
+use std::collections::{HashMap, HashSet};
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::spanned::Spanned;
+use syn::{braced, parenthesized, parse_macro_input, parse_quote, parse_quote_spanned, token,
+          Attribute, DeriveInput, Expr, ExprLit, Fields, Generics, Ident, Lit, LitInt, LitStr, Meta,
+          Token, Type, Variant, Visibility, WherePredicate};
 
 struct LongDescription {
-    format_str: Name,
-    format_args: Vec<P<Expr>>
+    format_str: LitStr,
+    format_args: Vec<Expr>,
 }
 
 struct VariantDef {
-    variant: P<Variant>,
-    short_description: Name,
+    variant: Variant,
+    short_description: LitStr,
     from_idx: Option<usize>,
+    from_with: Option<FromWith>,
+    source_idx: Option<usize>,
+    defaults: Vec<Option<Expr>>,
+    code: Option<Lit>,
+    exit_code: Option<LitInt>,
+    status: Option<LitInt>,
+    extensions: Vec<usize>,
+    transparent: bool,
     long_description: Option<LongDescription>,
 }
 
-fn expand_error_def<'c>(
-    cx: &'c mut ExtCtxt,
-    sp: Span,
-    type_name: ast::Ident,
-    tokens: Vec<TokenTree>
-) -> Box<MacResult + 'c> {
-    let mut parser = parse::stream_to_parser(cx.parse_sess(), tokens.into_iter().collect());
+/// Options which apply to a whole error type, given in an `#[error_def(...)]` attribute on it.
+#[derive(Default)]
+struct TypeOptions {
+    /// Implement the deprecated `Error::description` method, returning the short description.
+    description: bool,
+    /// The process exit code for variants without an `#[exit_code = N]` attribute.
+    exit_code: Option<LitInt>,
+    /// The HTTP status code for variants without a `#[status = N]` attribute.
+    status: Option<LitInt>,
+    /// The directory, relative to the crate's root, of the message catalogs used by `localized`.
+    catalogs: Option<LitStr>,
+    /// The locale whose catalog must have a message for every variant. Defaults to `en`.
+    default_locale: Option<LitStr>,
+    /// Generate a fieldless `Kind` enum with a variant for each of the type's variants.
+    kind: Option<Ident>,
+    /// Generate a constructor, an `is_*` predicate and `as_*` and `into_*` accessors for each
+    /// variant.
+    accessors: bool,
+    /// Generate `ResultExt` and `OptionExt` traits for lifting errors and `None`s into variants.
+    context: bool,
+    /// The text written between the short description, the long description and each error in
+    /// the chain by `{:#}`. Defaults to `". "`.
+    separator: Option<LitStr>,
+    /// Punctuation written at the end of every message, such as `"."`. Defaults to nothing.
+    punctuation: Option<LitStr>,
+    /// Implement `Serialize` and `Deserialize`, which needs the `serde` feature.
+    serde: bool,
+    /// Generate the `REFERENCE_JSON` and `REFERENCE_MARKDOWN` constants.
+    reference: bool,
+}
+
+impl TypeOptions {
+    /// Parse any `#[error_def(...)]` attributes in `attrs`, removing them.
+    fn take_from(attrs: &mut Vec<Attribute>) -> syn::Result<TypeOptions> {
+        let mut options = TypeOptions::default();
+        let mut errors = Errors::new();
+        attrs.retain(|attr| {
+            if !attr.path().is_ident("error_def") {
+                return true;
+            }
+            let res = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("description") {
+                    options.description = true;
+                    Ok(())
+                } else if meta.path.is_ident("exit_code") {
+                    options.exit_code = Some(parse_exit_code(meta.value()?)?);
+                    Ok(())
+                } else if meta.path.is_ident("status") {
+                    options.status = Some(parse_status(meta.value()?)?);
+                    Ok(())
+                } else if meta.path.is_ident("kind") {
+                    options.kind = meta.path.get_ident().cloned();
+                    Ok(())
+                } else if meta.path.is_ident("accessors") {
+                    options.accessors = true;
+                    Ok(())
+                } else if meta.path.is_ident("context") {
+                    options.context = true;
+                    Ok(())
+                } else if meta.path.is_ident("serde") {
+                    if !cfg!(feature = "serde") {
+                        return Err(meta.error("The serde option needs error_def's `serde` feature"));
+                    }
+                    options.serde = true;
+                    Ok(())
+                } else if meta.path.is_ident("reference") {
+                    options.reference = true;
+                    Ok(())
+                } else if meta.path.is_ident("separator") {
+                    options.separator = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("punctuation") {
+                    options.punctuation = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("catalogs") {
+                    options.catalogs = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("default_locale") {
+                    options.default_locale = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("Unknown error_def option"))
+                }
+            });
+            if let Err(e) = res {
+                errors.push(e);
+            }
+            false
+        });
+        errors.finish()?;
+        Ok(options)
+    }
+}
 
-    let mut items: Vec<P<ast::Item>> = Vec::new();
-    let mut variants: Vec<VariantDef> = Vec::new();
+/// Whether an error type is an enum with a variant for each kind of error, or a struct with a
+/// single kind of error. A struct is described by a single variant named after the struct.
+#[derive(Clone, Copy, PartialEq)]
+enum TypeKind {
+    Enum,
+    Struct,
+}
 
-    // Parse the token tree and populate our list of variants.
-    loop {
-        let variant_name = if parser.check(&Token::Eof) {
-            break
-        } else {
-            match parser.parse_ident() {
-                Ok(ident) => ident,
-                Err(mut e) => {
-                    e.emit();
-                    return DummyResult::any(sp);
+struct ErrorDef {
+    options: TypeOptions,
+    kind: TypeKind,
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    type_name: Ident,
+    generics: Generics,
+    variants: Vec<VariantDef>,
+}
+
+impl Parse for ErrorDef {
+    fn parse(input: ParseStream) -> syn::Result<ErrorDef> {
+        let mut attrs = input.call(Attribute::parse_outer)?;
+        let options = TypeOptions::take_from(&mut attrs)?;
+        // Error types have always been public by default. `pub(self)` makes one private.
+        let vis = match input.parse()? {
+            Visibility::Inherited => parse_quote!(pub),
+            vis => vis,
+        };
+        let struct_token: Option<Token![struct]> = input.parse()?;
+        let type_name: Ident = input.parse()?;
+        let mut generics: Generics = input.parse()?;
+        generics.where_clause = input.parse()?;
+
+        if struct_token.is_some() {
+            if let Some(ref kind) = options.kind {
+                return Err(syn::Error::new(kind.span(), "Only enums can have a kind enum"));
+            }
+
+            // The struct's attributes are kept with its variant, which is documented in the same
+            // way as an enum's variants.
+            let variant = VariantDef::parse_body(input, attrs, type_name.clone())?;
+            let variants = vec![variant];
+            let mut errors = Errors::new();
+            check_duplicates(&variants, &mut errors);
+            check_transparent(&variants, &mut errors);
+            errors.finish()?;
+
+            return Ok(ErrorDef {
+                options,
+                kind: TypeKind::Struct,
+                attrs: Vec::new(),
+                vis,
+                type_name,
+                generics,
+                variants,
+            });
+        }
+
+        let content;
+        braced!(content in input);
+
+        // Parse the token tree and populate our list of variants. If a variant fails to parse we
+        // skip to the next one so that all of the errors can be reported at once.
+        let mut variants: Vec<VariantDef> = Vec::new();
+        let mut errors = Errors::new();
+        while !content.is_empty() {
+            match content.parse() {
+                Ok(variant) => variants.push(variant),
+                Err(e) => {
+                    errors.push(e);
+                    skip_past_comma(&content)?;
+                    continue;
                 },
             }
+            if content.is_empty() {
+                break;
+            }
+            if let Err(e) = content.parse::<Token![,]>() {
+                errors.push(e);
+                skip_past_comma(&content)?;
+            }
+        }
+
+        check_duplicates(&variants, &mut errors);
+        check_from_impls(&variants, &mut errors);
+        check_codes(&variants, &mut errors);
+        check_transparent(&variants, &mut errors);
+        errors.finish()?;
+
+        Ok(ErrorDef {
+            options,
+            kind: TypeKind::Enum,
+            attrs,
+            vis,
+            type_name,
+            generics,
+            variants,
+        })
+    }
+}
+
+/// Accumulates errors so that they can all be reported together.
+struct Errors(Option<syn::Error>);
+
+impl Errors {
+    fn new() -> Errors {
+        Errors(None)
+    }
+
+    fn push(&mut self, e: syn::Error) {
+        match self.0 {
+            Some(ref mut errors) => errors.combine(e),
+            None => self.0 = Some(e),
+        }
+    }
+
+    fn finish(self) -> syn::Result<()> {
+        match self.0 {
+            Some(errors) => Err(errors),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Skip the remainder of a variant that failed to parse, up to and including the comma which
+/// separates it from the next variant.
+fn skip_past_comma(input: ParseStream) -> syn::Result<()> {
+    input.step(|cursor| {
+        let mut rest = *cursor;
+        while let Some((tt, next)) = rest.token_tree() {
+            rest = next;
+            if let TokenTree::Punct(ref punct) = tt {
+                if punct.as_char() == ',' {
+                    break;
+                }
+            }
+        }
+        Ok(((), rest))
+    })
+}
+
+/// Parse a variant's error code, which must be a string or integer literal.
+fn parse_code(input: ParseStream) -> syn::Result<Lit> {
+    match input.parse()? {
+        code @ (Lit::Str(..) | Lit::Int(..)) => Ok(code),
+        code => Err(syn::Error::new_spanned(code, "Error codes must be string or integer literals")),
+    }
+}
+
+/// Parse a process exit code, which must be an integer literal between 1 and 255. 0 would report
+/// success, and larger codes can't be returned portably.
+fn parse_exit_code(input: ParseStream) -> syn::Result<LitInt> {
+    let exit_code: LitInt = input.parse()?;
+    if !(1..=255).contains(&exit_code.base10_parse::<u16>().unwrap_or(0)) {
+        return Err(syn::Error::new(exit_code.span(), "Exit codes must be between 1 and 255"));
+    }
+    // Drop any suffix, as the code is returned as a `u8`.
+    Ok(LitInt::new(exit_code.base10_digits(), exit_code.span()))
+}
+
+/// Parse an HTTP status code, which must be an integer literal between 100 and 599.
+fn parse_status(input: ParseStream) -> syn::Result<LitInt> {
+    let status: LitInt = input.parse()?;
+    if !(100..=599).contains(&status.base10_parse::<u16>()?) {
+        return Err(syn::Error::new(status.span(), "HTTP status codes must be between 100 and 599"));
+    }
+    Ok(status)
+}
+
+/// Find a variant's `#[name = N]` attribute, such as `#[exit_code = N]`, if any, removing it from
+/// `attrs` and parsing its value with `parse`.
+fn take_int_attr(
+    attrs: &mut Vec<Attribute>,
+    name: &str,
+    parse: fn(ParseStream) -> syn::Result<LitInt>,
+) -> syn::Result<Option<LitInt>> {
+    let mut value = None;
+    let mut errors = Errors::new();
+    attrs.retain(|attr| {
+        if !attr.path().is_ident(name) {
+            return true;
+        }
+        let res = match attr.meta {
+            Meta::NameValue(ref meta) if value.is_none() => {
+                parse.parse2(meta.value.to_token_stream()).map(|lit| value = Some(lit))
+            },
+            Meta::NameValue(..) => Err(syn::Error::new_spanned(attr, format!("Duplicate #[{}] attribute", name))),
+            _ => Err(syn::Error::new_spanned(attr, format!("Expected #[{} = N]", name))),
         };
+        if let Err(e) = res {
+            errors.push(e);
+        }
+        false
+    });
+    errors.finish()?;
+    Ok(value)
+}
 
-        let (from_idx, members): (Option<usize>, Option<Vec<StructField>>) = if parser.eat(&Token::FatArrow) {
-            // It's a unit-like variant. (ie. not a struct variant)
-            (None, None)
-        } else if parser.eat(&Token::OpenDelim(DelimToken::Brace)) {
-            // It's a struct variant
-            let mut members: Vec<StructField> = Vec::new();
-            let mut from_memb_idx: Option<usize> = None;
-
-            // Parse the list of struct members.
-            loop {
-                
-                // Parse the list of attributes on this struct member
-                let mut attrs = match parser.parse_outer_attributes() {
-                    Ok(attrs) => attrs,
-                    Err(mut e) => {
-                        e.emit();
-                        return DummyResult::any(sp);
+/// Find a variant's `#[transparent]` attribute, if any, removing it from `attrs`.
+fn take_transparent(attrs: &mut Vec<Attribute>) -> syn::Result<bool> {
+    let mut transparent = false;
+    let mut errors = Errors::new();
+    attrs.retain(|attr| {
+        if !attr.path().is_ident("transparent") {
+            return true;
+        }
+        if let Err(e) = attr.meta.require_path_only() {
+            errors.push(e);
+        } else if std::mem::replace(&mut transparent, true) {
+            errors.push(syn::Error::new_spanned(attr, "Duplicate #[transparent] attribute"));
+        }
+        false
+    });
+    errors.finish()?;
+    Ok(transparent)
+}
+
+/// Check that every `#[transparent]` variant wraps a single field and has no long description of
+/// its own.
+fn check_transparent(variants: &[VariantDef], errors: &mut Errors) {
+    for v in variants.iter().filter(|v| v.transparent) {
+        if v.variant.fields.len() != 1 {
+            errors.push(syn::Error::new(
+                v.variant.ident.span(),
+                "A #[transparent] variant must have exactly one field",
+            ));
+        }
+        if let Some(ref long_description) = v.long_description {
+            errors.push(syn::Error::new(
+                long_description.format_str.span(),
+                "A #[transparent] variant is displayed by its field and cannot have a long description",
+            ));
+        }
+    }
+}
+
+/// Check that either every variant or no variant has an error code, that the codes are all
+/// strings or all integers of the same type, and that no two variants share a code.
+fn check_codes(variants: &[VariantDef], errors: &mut Errors) {
+    let first = match variants.iter().find(|v| v.code.is_some()) {
+        Some(first) => first,
+        None => return,
+    };
+    let first_code = first.code.as_ref().unwrap();
+
+    let mut int_suffix: Option<&LitInt> = None;
+    let mut codes: HashMap<String, &Ident> = HashMap::new();
+    for v in variants {
+        let code = match v.code {
+            Some(ref code) => code,
+            None => {
+                errors.push(syn::Error::new(
+                    v.variant.ident.span(),
+                    format!(
+                        "Variant `{}` has no error code; either every variant or none must have one",
+                        v.variant.ident,
+                    ),
+                ));
+                continue;
+            },
+        };
+
+        let value = match (first_code, code) {
+            (Lit::Str(..), Lit::Str(code)) => code.value(),
+            (Lit::Int(..), Lit::Int(code)) => {
+                if !code.suffix().is_empty() {
+                    match int_suffix {
+                        Some(suffix) if suffix.suffix() != code.suffix() => {
+                            errors.push(syn::Error::new(
+                                code.span(),
+                                format!(
+                                    "Error code has type `{}` but `{}` was used before",
+                                    code.suffix(),
+                                    suffix.suffix(),
+                                ),
+                            ));
+                        },
+                        Some(..) => (),
+                        None => int_suffix = Some(code),
+                    }
+                }
+                code.base10_digits().to_owned()
+            },
+            _ => {
+                errors.push(syn::Error::new_spanned(
+                    code,
+                    format!("Error codes must all be of the same kind as `{}`", first_code.to_token_stream()),
+                ));
+                continue;
+            },
+        };
+        if let Some(other) = codes.insert(value, &v.variant.ident) {
+            errors.push(syn::Error::new_spanned(
+                code,
+                format!("Variant `{}` already has this error code", other),
+            ));
+        }
+    }
+}
+
+/// The type of the variants' error codes, if they have them. Integer codes are `u32` unless a
+/// suffix is given.
+fn code_type(variants: &[VariantDef]) -> Option<Type> {
+    let mut codes = variants.iter().filter_map(|v| v.code.as_ref());
+    match *codes.next()? {
+        Lit::Str(..) => Some(parse_quote!(&'static str)),
+        _ => {
+            let suffixed = variants.iter().find_map(|v| match v.code {
+                Some(Lit::Int(ref code)) if !code.suffix().is_empty() => Some(code.suffix()),
+                _ => None,
+            });
+            let ty = Ident::new(suffixed.unwrap_or("u32"), Span::call_site());
+            Some(parse_quote!(#ty))
+        },
+    }
+}
+
+/// Report any types which more than one variant wants to generate a `From` impl for. Variants with
+/// `#[cfg]` attributes are skipped since they may never be enabled at the same time.
+fn check_from_impls(variants: &[VariantDef], errors: &mut Errors) {
+    let mut from_types: HashMap<String, &Ident> = HashMap::new();
+    for v in variants {
+        let from_idx = match v.converted_member_idx() {
+            Some(from_idx) => from_idx,
+            None => continue,
+        };
+        if v.variant.attrs.iter().any(|attr| attr.path().is_ident("cfg")) {
+            continue;
+        }
+        let ty = v.converted_type(from_idx);
+        let key = ty.to_token_stream().to_string();
+        if let Some(other) = from_types.insert(key, &v.variant.ident) {
+            errors.push(syn::Error::new(
+                ty.span(),
+                format!(
+                    "Variant `{}` already converts from this type; use #[source] instead of #[from] here",
+                    other,
+                ),
+            ));
+        }
+    }
+}
+
+/// Report any variants which share a name, and any fields which share a name within a variant.
+fn check_duplicates(variants: &[VariantDef], errors: &mut Errors) {
+    let mut variant_names: HashSet<&Ident> = HashSet::new();
+    for v in variants {
+        let name = &v.variant.ident;
+        if !variant_names.insert(name) {
+            errors.push(syn::Error::new(name.span(), format!("Duplicate variant `{}`", name)));
+        }
+
+        let mut field_names: HashSet<&Ident> = HashSet::new();
+        for field in &v.variant.fields {
+            if let Some(ref ident) = field.ident {
+                if !field_names.insert(ident) {
+                    errors.push(syn::Error::new(ident.span(), format!("Duplicate field `{}`", ident)));
+                }
+            }
+        }
+    }
+}
+
+impl Parse for LongDescription {
+    fn parse(input: ParseStream) -> syn::Result<LongDescription> {
+        let format_str: LitStr = input.parse()?;
+        let mut format_args: Vec<Expr> = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            format_args.push(input.parse()?);
+        }
+
+        Ok(LongDescription {
+            format_str,
+            format_args,
+        })
+    }
+}
+
+impl LongDescription {
+    /// Resolve references to the variant's fields in the format string and arguments.
+    ///
+    /// In a tuple variant, format arguments which are bare integers (eg. `0`) refer to the
+    /// variant's fields, and are replaced with the names that those fields are bound to. Named
+    /// placeholders in the format string (eg. `{flim}`) which aren't given as named arguments
+    /// capture the field of the same name.
+    fn bind_fields(&mut self, fields: &Fields) -> syn::Result<()> {
+        let bindings = field_bindings(fields);
+        if let Fields::Unnamed(..) = *fields {
+            for arg in &mut self.format_args {
+                let idx = match *arg {
+                    Expr::Lit(ExprLit { lit: Lit::Int(ref lit), .. }) if lit.suffix().is_empty() => {
+                        lit.base10_parse::<usize>()?
                     },
+                    _ => continue,
                 };
+                match bindings.get(idx) {
+                    Some(binding) => *arg = parse_quote!(#binding),
+                    None => {
+                        return Err(syn::Error::new_spanned(arg, format!("Variant has no field {}", idx)));
+                    },
+                }
+            }
+        }
 
-                // Find whether this member is marked #[from]. And if it is, find the index of the
-                // #[from] attribute so we can remove it.
-
-                let mut from_attr_idx: Option<(usize, Span)> = None;
-                for (i, attr) in attrs.iter().enumerate() {
-                    if attr.path == "from" {
-                        match from_attr_idx {
-                            Some(_) => {
-                                cx.span_err(attr.span, "Field marked #[from] twice");
-                                return DummyResult::any(sp);
-                            },
-                            None => from_attr_idx = Some((i, attr.span)),
-                        };
-                    };
+        let mut named_args: HashSet<String> = HashSet::new();
+        for arg in &self.format_args {
+            if let Expr::Assign(ref assign) = *arg {
+                if let Expr::Path(ref path) = *assign.left {
+                    if let Some(ident) = path.path.get_ident() {
+                        named_args.insert(ident.to_string());
+                    }
+                }
+            }
+        }
+
+        let mut errors = Errors::new();
+        for name in format_string_names(&self.format_str.value()) {
+            if named_args.contains(&name) {
+                continue;
+            }
+            if bindings.iter().any(|binding| *binding == name) {
+                let binding = Ident::new(&name, self.format_str.span());
+                self.format_args.push(parse_quote!(#binding = #binding));
+            } else {
+                errors.push(syn::Error::new(
+                    self.format_str.span(),
+                    format!("`{{{}}}` does not refer to a field of this variant or to a named argument", name),
+                ));
+            }
+            named_args.insert(name);
+        }
+        errors.finish()
+    }
+}
+
+/// The names referred to by the placeholders in a format string. This includes the arguments
+/// of placeholders such as `{flim}` and `{flam:?}` as well as the width and precision parameters
+/// in placeholders such as `{:width$.prec$}`. Positional references are skipped.
+fn format_string_names(format_str: &str) -> Vec<String> {
+    fn is_name(s: &str) -> bool {
+        s.starts_with(|c: char| c.is_alphabetic() || c == '_') && s != "_"
+    }
+
+    let mut names = Vec::new();
+    let mut chars = format_str.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '}' {
+            // Skip `}}` escapes.
+            chars.next_if_eq(&'}');
+            continue;
+        }
+        if c != '{' {
+            continue;
+        }
+        if chars.next_if_eq(&'{').is_some() {
+            continue;
+        }
+
+        let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+        let (arg, spec) = match placeholder.find(':') {
+            Some(i) => (&placeholder[..i], &placeholder[i + 1..]),
+            None => (&placeholder[..], ""),
+        };
+        let arg = arg.trim();
+        if is_name(arg) {
+            names.push(arg.to_owned());
+        }
+
+        // Find any `name$` parameters in the format spec.
+        let mut start = 0;
+        for (i, c) in spec.char_indices() {
+            if c == '$' {
+                if is_name(&spec[start..i]) {
+                    names.push(spec[start..i].to_owned());
+                }
+            } else if c.is_alphanumeric() || c == '_' {
+                continue;
+            }
+            start = i + c.len_utf8();
+        }
+    }
+    names
+}
+
+/// A conversion function given in a `#[from(Type, with = function)]` attribute.
+struct FromWith {
+    ty: Type,
+    function: Expr,
+}
+
+mod kw {
+    syn::custom_keyword!(with);
+}
+
+impl Parse for FromWith {
+    fn parse(input: ParseStream) -> syn::Result<FromWith> {
+        // The source type can't be inferred from the function, so `#[from(with = f)]` is an error.
+        if input.peek(kw::with) && input.peek2(Token![=]) {
+            return Err(input.error("The source type is required: #[from(Type, with = function)]"));
+        }
+        let ty: Type = input.parse()?;
+        input.parse::<Token![,]>()?;
+        input.parse::<kw::with>()?;
+        input.parse::<Token![=]>()?;
+        let function: Expr = input.parse()?;
+        Ok(FromWith {
+            ty,
+            function,
+        })
+    }
+}
+
+/// The members of a variant which are marked `#[from]`, `#[source]`, `#[default]` or
+/// `#[extension]`.
+#[derive(Default)]
+struct MemberMarkers {
+    from_idx: Option<usize>,
+    from_with: Option<FromWith>,
+    source_idx: Option<usize>,
+    defaults: Vec<Option<Expr>>,
+    extensions: Vec<usize>,
+}
+
+impl MemberMarkers {
+    /// Find the members of a variant which are marked `#[from]`, `#[source]`, `#[default]` or
+    /// `#[extension]`, and remove those pseudo-attributes. A `#[from]` member is also the variant's
+    /// source, unless its value is converted by a `with` function.
+    fn take_from(fields: &mut Fields) -> syn::Result<MemberMarkers> {
+        let mut markers = MemberMarkers::default();
+        let mut errors = Errors::new();
+        for (i, member) in fields.iter_mut().enumerate() {
+            let mut is_from = false;
+            let mut from_with: Option<FromWith> = None;
+            let mut is_source = false;
+            let mut default: Option<Expr> = None;
+            let mut is_extension = false;
+            member.attrs.retain(|attr| {
+                let name = match attr.path().get_ident() {
+                    Some(name) => name,
+                    None => return true,
                 };
-                match from_attr_idx {
-                    // This member is marked #[from]. Record this.
-                    Some((i, attr_span)) => {
-                        attrs.swap_remove(i);
-                        match from_memb_idx {
-                            Some(_) => {
-                                cx.span_err(attr_span, "Multiple fields marked #[from]");
-                                return DummyResult::any(sp);
-                            },
-                            None  => from_memb_idx = Some(members.len()),
-                        };
-                    },
-                    None    => (),
+                let already_marked = if name == "from" {
+                    match attr.meta {
+                        Meta::Path(..) => (),
+                        Meta::List(..) => match attr.parse_args() {
+                            Ok(with) => from_with = Some(with),
+                            Err(e) => errors.push(e),
+                        },
+                        Meta::NameValue(..) => {
+                            errors.push(syn::Error::new_spanned(
+                                attr,
+                                "Expected #[from] or #[from(Type, with = function)]",
+                            ));
+                        },
+                    }
+                    std::mem::replace(&mut is_from, true)
+                } else if name == "source" {
+                    std::mem::replace(&mut is_source, true)
+                } else if name == "default" {
+                    match parse_default(attr) {
+                        Ok(expr) => default.replace(expr).is_some(),
+                        Err(e) => {
+                            errors.push(e);
+                            false
+                        },
+                    }
+                } else if name == "extension" {
+                    std::mem::replace(&mut is_extension, true)
+                } else {
+                    return true;
                 };
+                if already_marked {
+                    errors.push(syn::Error::new_spanned(attr, format!("Field marked #[{}] twice", name)));
+                }
+                false
+            });
 
-                // Parse the name and type of the member.
-                let sf = match parser.parse_single_struct_field(DUMMY_SP,
-                                                                Visibility::Inherited,
-                                                                attrs) {
-                    Ok(sf)  => sf,
-                    Err(mut e)  => {
-                        e.emit();
-                        return DummyResult::any(sp);
+            let is_converted = from_with.is_some();
+            if is_from {
+                if markers.from_idx.is_some() {
+                    errors.push(syn::Error::new(member.span(), "Multiple fields marked #[from]"));
+                }
+                markers.from_idx = Some(i);
+                markers.from_with = from_with;
+            }
+            if (is_from && !is_converted) || is_source {
+                if markers.source_idx.is_some_and(|idx| idx != i) {
+                    errors.push(syn::Error::new(member.span(), "Multiple fields marked #[source] or #[from]"));
+                }
+                markers.source_idx = Some(i);
+            }
+            if is_extension {
+                match member.ident {
+                    Some(ref ident) if RESERVED_PROBLEM_MEMBERS.iter().any(|reserved| ident == reserved) => {
+                        errors.push(syn::Error::new(
+                            ident.span(),
+                            format!("`{}` is a standard problem details member and cannot be an #[extension]", ident),
+                        ));
+                    },
+                    Some(..) => markers.extensions.push(i),
+                    None => {
+                        errors.push(syn::Error::new(member.span(), "Only named fields can be marked #[extension]"));
                     },
-                };
-                if sf.ident.is_none() {
-                    cx.span_err(sp, "Expected a named field");
-                    return DummyResult::any(sp);
                 }
-                members.push(sf);
-                if parser.token == Token::CloseDelim(DelimToken::Brace) {
-                    let _ = parser.bump();
-                    break;
+            }
+            markers.defaults.push(default);
+        }
+
+        // #[default] values are only used to fill in the other fields in a `From` impl.
+        for (i, default) in markers.defaults.iter().enumerate() {
+            if let Some(ref default) = *default {
+                match markers.from_idx {
+                    None => {
+                        errors.push(syn::Error::new_spanned(
+                            default,
+                            "#[default] can only be used in a variant with a #[from] field",
+                        ));
+                    },
+                    Some(from_idx) if from_idx == i => {
+                        errors.push(syn::Error::new_spanned(default, "A #[from] field cannot have a #[default]"));
+                    },
+                    Some(..) => (),
                 }
+            }
+        }
 
-            };
-      
-            if let Err(mut e) = parser.expect(&Token::FatArrow) {
-                e.emit();
-                return DummyResult::any(sp);
-            };
+        errors.finish()?;
+        Ok(markers)
+    }
+}
+
+/// The members of an RFC 7807 problem details object which `#[extension]` fields can't replace.
+const RESERVED_PROBLEM_MEMBERS: &[&str] = &["type", "title", "status", "detail", "instance"];
+
+/// Parse a `#[default]` or `#[default = expr]` attribute into the expression for the default value.
+fn parse_default(attr: &Attribute) -> syn::Result<Expr> {
+    match attr.meta {
+        Meta::Path(ref path) => Ok(parse_quote_spanned!(path.span()=> ::std::default::Default::default())),
+        Meta::NameValue(ref name_value) => Ok(name_value.value.clone()),
+        Meta::List(..) => Err(syn::Error::new_spanned(attr, "Expected #[default] or #[default = expr]")),
+    }
+}
+
+impl VariantDef {
+    /// The type that the `From` impl for the member at `from_idx` converts from.
+    fn converted_type(&self, from_idx: usize) -> &Type {
+        match self.from_with {
+            Some(ref from_with) => &from_with.ty,
+            None => &self.variant.fields.iter().nth(from_idx).unwrap().ty,
+        }
+    }
 
-            (from_memb_idx, Some(members))
+    /// The index of the member that a `From` impl should be generated for. This is the member
+    /// marked `#[from]`, provided that every other member has a `#[default]`.
+    fn converted_member_idx(&self) -> Option<usize> {
+        let from_idx = self.from_idx?;
+        let others_defaulted = self
+            .defaults
+            .iter()
+            .enumerate()
+            .all(|(i, default)| i == from_idx || default.is_some());
+        if others_defaulted {
+            Some(from_idx)
         } else {
-            match parser.expect_one_of(&[Token::FatArrow, Token::OpenDelim(DelimToken::Brace)], &[]) {
-                Ok(..) => unreachable!(),
-                Err(mut e) => {
-                    e.emit();
-                    return DummyResult::any(sp);
-                },
-            }
-        };
+            None
+        }
+    }
+}
 
-        // Parse the short description.
-        let short_desc = match parser.parse_str() {
-            Ok((sd, _)) => sd,
-            Err(mut e) => {
-                e.emit();
-                return DummyResult::any(sp);
-            },
-        };
+impl Parse for VariantDef {
+    fn parse(input: ParseStream) -> syn::Result<VariantDef> {
+        let user_attrs = input.call(Attribute::parse_outer)?;
+        let variant_name: Ident = input.parse()?;
+        VariantDef::parse_body(input, user_attrs, variant_name)
+    }
+}
 
-        // Parse the long description if it exists.
-        let long_desc = if parser.token == Token::OpenDelim(DelimToken::Paren) {
-            let _ = parser.bump();
-
-            let format_str = match parser.parse_str() {
-                Ok((fs, _)) => fs,
-                Err(mut e) => {
-                    e.emit();
-                    return DummyResult::any(sp);
-                },
-            };
+impl VariantDef {
+    /// Parse the rest of a variant after its attributes and name: its fields, error code and
+    /// descriptions.
+    fn parse_body(input: ParseStream, mut user_attrs: Vec<Attribute>, variant_name: Ident) -> syn::Result<VariantDef> {
+        let exit_code = take_int_attr(&mut user_attrs, "exit_code", parse_exit_code)?;
+        let status = take_int_attr(&mut user_attrs, "status", parse_status)?;
+        let transparent = take_transparent(&mut user_attrs)?;
 
-            let mut format_args: Vec<P<Expr>> = Vec::new();
-            loop {
-                if parser.eat(&Token::CloseDelim(DelimToken::Paren)) {
-                    break
-                } else if let Err(mut e) = parser.expect(&Token::Comma) {
-                    e.emit();
-                    return DummyResult::any(sp);
-                };
-                let ex = match parser.parse_expr() {
-                    Ok(ex) => ex,
-                    Err(mut e) => {
-                        e.emit();
-                        return DummyResult::any(sp);
-                    },
-                };
-                format_args.push(ex);
-            };
+        let lookahead = input.lookahead1();
+        let mut fields = if lookahead.peek(Token![=>]) || lookahead.peek(Token![=]) {
+            // It's a unit-like variant. (ie. not a struct or tuple variant)
+            Fields::Unit
+        } else if lookahead.peek(token::Brace) {
+            // It's a struct variant
+            Fields::Named(input.parse()?)
+        } else if lookahead.peek(token::Paren) {
+            // It's a tuple variant
+            Fields::Unnamed(input.parse()?)
+        } else {
+            return Err(lookahead.error());
+        };
+        let MemberMarkers {
+            from_idx,
+            from_with,
+            source_idx,
+            defaults,
+            extensions,
+        } = MemberMarkers::take_from(&mut fields)?;
 
-            Some(LongDescription {
-                format_str: format_str,
-                format_args: format_args,
-            })
+        // Parse the error code if it exists.
+        let code = if input.peek(Token![=]) && !input.peek(Token![=>]) {
+            input.parse::<Token![=]>()?;
+            Some(parse_code(input)?)
         } else {
             None
         };
+        input.parse::<Token![=>]>()?;
 
-        let comment_str = format!("/// {}.", short_desc);
-        let comment = Symbol::intern(&comment_str[..]);
+        // Parse the short description.
+        let short_description: LitStr = input.parse()?;
 
-        // Build our variant definition out of the information we've parsed.
-        variants.push(VariantDef {
-            variant: P(dummy_spanned(Variant_ {
-                name:      variant_name,
-                attrs:     vec![mk_sugared_doc_attr(mk_attr_id(), comment, DUMMY_SP)],
-                data:      match members {
-                    None => VariantData::Unit(DUMMY_NODE_ID),
-                    Some(members) => VariantData::Struct(members, DUMMY_NODE_ID),
-                },
-                disr_expr: None,
-            })),
-            short_description: short_desc,
-            from_idx: from_idx,
-            long_description: long_desc,
-        }); 
+        // Parse the long description if it exists.
+        let long_description = if input.peek(token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            let mut long_description: LongDescription = content.parse()?;
+            long_description.bind_fields(&fields)?;
+            Some(long_description)
+        } else {
+            None
+        };
 
-        if let Err(mut e) = parser.expect_one_of(&[Token::Comma], &[Token::Eof]) {
-            e.emit();
-            return DummyResult::any(sp);
+        // Document the variant with its short description, followed by any doc comments the user
+        // wrote on the variant as a separate paragraph.
+        let comment = format!(" {}.", short_description.value());
+        let mut attrs: Vec<Attribute> = vec![parse_quote!(#[doc = #comment])];
+        if user_attrs.iter().any(|attr| attr.path().is_ident("doc")) {
+            attrs.push(parse_quote!(#[doc = ""]));
         }
+        attrs.extend(user_attrs);
+
+        // Build our variant definition out of the information we've parsed.
+        Ok(VariantDef {
+            variant: Variant {
+                attrs,
+                ident: variant_name,
+                fields,
+                discriminant: None,
+            },
+            short_description,
+            from_idx,
+            from_with,
+            source_idx,
+            defaults,
+            code,
+            exit_code,
+            status,
+            extensions,
+            transparent,
+            long_description,
+        })
     }
+}
 
-    // Helper wrappers for missing ToTokens impls
+fn expand_error_def(def: ErrorDef) -> TokenStream {
+    let ErrorDef {
+        ref attrs,
+        ref vis,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = def;
 
-    struct StructFieldWrapper(StructField);
-    impl ToTokens for StructFieldWrapper {
-        fn to_tokens(&self, cx: &ExtCtxt) -> Vec<TokenTree> {
-            let StructFieldWrapper(ref struct_field) = *self;
-            let StructField {
-                ref ty,
-                ref ident,
-                ref attrs,
-                ..
-            } = *struct_field;
+    // Add the enum, or the struct
 
-            quote_tokens!(cx, $attrs $ident: $ty,)
-        }
-    }
-
-    struct VariantWrapper(Variant);
-    impl ToTokens for VariantWrapper {
-        fn to_tokens(&self, cx: &ExtCtxt) -> Vec<TokenTree> {
-            let VariantWrapper(ref variant) = *self;
-            let Variant_ {
+    let where_clause = &generics.where_clause;
+    let the_type = match def.kind {
+        TypeKind::Enum => {
+            let variants_wrapped = variants.iter().map(|v| &v.variant);
+            quote! {
+                #(#attrs)*
+                #vis enum #type_name #generics #where_clause {
+                    #(#variants_wrapped,)*
+                }
+            }
+        },
+        TypeKind::Struct => {
+            let Variant {
                 ref attrs,
-                ref name,
-                ref data,
+                ref fields,
                 ..
-            } = variant.node;
-
-            match *data {
-                VariantData::Unit(..) => quote_tokens!(cx, $attrs $name,),
-                VariantData::Struct(ref members, _) => {
-                    let members_wrapped: Vec<_> = members.iter().map(|m| StructFieldWrapper(m.clone())).collect();
-                    quote_tokens!(cx, $attrs $name {
-                        $members_wrapped
-                    },)
+            } = variants[0].variant;
+            match *fields {
+                Fields::Named(..) => quote! {
+                    #(#attrs)*
+                    #vis struct #type_name #generics #where_clause #fields
+                },
+                Fields::Unnamed(..) => quote! {
+                    #(#attrs)*
+                    #vis struct #type_name #generics #fields #where_clause;
+                },
+                Fields::Unit => quote! {
+                    #(#attrs)*
+                    #vis struct #type_name #generics #where_clause;
                 },
-                _ => unreachable!(),
             }
-        }
-    }
-
+        },
+    };
 
-    // Add the enum
-
-    let mut variants_wrapped = Vec::new();
-    for v in &variants {
-        let variant = VariantWrapper((*v.variant).clone());
-        variants_wrapped.push(variant);
+    let impls = expand_impls(&def);
+    quote! {
+        #the_type
+        #impls
     }
+}
 
-    let the_enum = quote_item!(cx, pub enum $type_name {
-        $variants_wrapped
-    });
+/// Generate the `Debug`, `Display`, `Error` and `From` impls for an error type. This is shared
+/// between `error_def!`, which also emits the enum or struct itself, and `#[derive(ErrorDef)]`.
+fn expand_impls(def: &ErrorDef) -> TokenStream {
+    let ErrorDef {
+        ref options,
+        ref vis,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
 
-    items.push(the_enum.unwrap());
+    let mut items: Vec<TokenStream> = Vec::new();
+    let (_, ty_generics, _) = generics.split_for_impl();
 
     // Add Debug impl
 
     let mut debug_impl_arms = Vec::new();
-    for v in &variants {
+    let mut debug_bounds: Vec<WherePredicate> = vec![parse_quote!(Self: ::std::fmt::Display)];
+    for v in variants {
         let VariantDef {
             ref variant,
             ..
         } = *v;
-        let Variant_ {
-            ref name,
-            ref data,
+        let Variant {
+            ident: ref name,
+            ref fields,
             ..
-        } = variant.node;
-
-        let full_name = Symbol::intern(&format!("{}::{}", type_name, name));
-        let full_name = dummy_spanned(ast::LitKind::Str(full_name, ast::StrStyle::Cooked));
-        let debug_impl_arm = match *data {
-            VariantData::Unit(..) => {
-                quote_tokens!(cx, $type_name::$name => {
-                    write!(f, $full_name)?;
-                    write!(f, " /* {} */", self)?;
-                })
+        } = *variant;
+
+        for member in fields {
+            let ty = &member.ty;
+            if mentions_type_param(ty, generics) {
+                debug_bounds.push(parse_quote!(#ty: ::std::fmt::Debug));
+            }
+        }
+
+        let forwarded_attrs = forwarded_attrs(variant);
+        let pattern = def.variant_pattern(variant);
+        let bindings = field_bindings(fields);
+        let full_name = match def.kind {
+            TypeKind::Enum => format!("{}::{}", type_name, name),
+            TypeKind::Struct => type_name.to_string(),
+        };
+        let body = match *fields {
+            Fields::Unit => {
+                quote! {
+                    f.write_str(#full_name)?;
+                }
+            },
+            Fields::Named(..) => {
+                let ident_lits = bindings.iter().map(|i| i.to_string());
+                quote! {
+                    f.debug_struct(#full_name)
+                        #(.field(#ident_lits, #bindings))*
+                        .finish()?;
+                }
             },
-            VariantData::Struct(ref members, ..) => {
-                let mut ms = Vec::new();
-                let mut body = quote_tokens!(cx, f.debug_struct($full_name));
-                for member in members {
-                    let StructField {
-                        ref ident,
-                        ..
-                    } = *member;
-                    let ident = ident.as_ref().unwrap();
-                    ms.extend(quote_tokens!(cx, ref $ident,));
-                    let ident_lit = dummy_spanned(ast::LitKind::Str(ident.name, ast::StrStyle::Cooked));
-                    body.extend(quote_tokens!(cx, .field($ident_lit, $ident)));
-                }
-                body.extend(quote_tokens!(cx, .finish()?));
-
-                quote_tokens!(cx, $type_name::$name { $ms } => {
-                    $body;
-                    write!(f, " /* {} */", self)?;
-                })
+            Fields::Unnamed(..) => {
+                quote! {
+                    f.debug_tuple(#full_name)
+                        #(.field(#bindings))*
+                        .finish()?;
+                }
             },
-            _ => unreachable!(),
         };
-        debug_impl_arms.push(debug_impl_arm);
+        debug_impl_arms.push(quote! {
+            #forwarded_attrs
+            #pattern => {
+                #body
+                write!(f, " /* {:#} */", self)?;
+            }
+        });
     }
 
-    let debug_impl = quote_item!(cx, impl ::std::fmt::Debug for $type_name {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-            match *self {
-                $debug_impl_arms
+    let debug_generics = with_bounds(generics, debug_bounds);
+    let (impl_generics, _, where_clause) = debug_generics.split_for_impl();
+    items.push(quote! {
+        impl #impl_generics ::std::fmt::Debug for #type_name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                match *self {
+                    #(#debug_impl_arms)*
+                }
+                Ok(())
             }
-            Ok(())
         }
     });
 
-    items.push(debug_impl.unwrap());
-
     // Add Display impl
 
+    // `{}` writes just the short description, and `{:#}` adds the long description and the chain
+    // of errors which caused this one. `report()` writes the long description without the chain, as
+    // it writes the causes on their own lines.
+    let separator = match options.separator {
+        Some(ref separator) => quote!(#separator),
+        None => quote!(". "),
+    };
+    let punctuation = match options.punctuation {
+        Some(ref punctuation) => quote!(f.write_str(#punctuation)?;),
+        None => TokenStream::new(),
+    };
     let mut display_impl_arms = Vec::new();
-    for v in &variants {
+    let mut details_impl_arms = Vec::new();
+    let mut display_bounds: Vec<WherePredicate> = Vec::new();
+    for v in variants {
         let VariantDef {
             ref variant,
             ref short_description,
+            ref source_idx,
             ref long_description,
             ..
         } = *v;
-        let Variant_ {
-            ref name,
-            ref data,
-            ..
-        } = variant.node;
-        
-        let short = dummy_spanned(ast::LitKind::Str(short_description.clone(), ast::StrStyle::Cooked));
-        let print_short = quote_stmt!(cx, write!(f, $short)?;); 
-        let mut body = quote_tokens!(cx, $print_short);
-        if let Some(LongDescription { ref format_str, ref format_args }) = *long_description {
-            let long_fmt = format!(". {}", format_str);
-            let long_fmt = Symbol::intern(&long_fmt[..]);
-            let long = dummy_spanned(ast::LitKind::Str(long_fmt, ast::StrStyle::Cooked));
-            let mut args = Vec::new();
-            for arg in format_args {
-                args.extend(quote_tokens!(cx, $arg,));
-            }
-            let print_long = quote_stmt!(cx, write!(f, $long, $args)?;);
-            body.extend(quote_tokens!(cx, $print_long));
-        }
-
-        let display_impl_arm = match *data {
-            VariantData::Unit(..) => {
-                quote_tokens!(cx, $type_name::$name => {
-                    $body
-                })
-            },
-            VariantData::Struct(ref members, ..) => {
-                let mut ms = Vec::new();
-                for member in members {
-                    let StructField {
-                        ref ident,
-                        ..
-                    } = *member;
-                    ms.extend(quote_tokens!(cx, ref $ident,));
-                }
-                quote_tokens!(cx, $type_name::$name { $ms } => {
-                    $body
-                })
-            },
-            _ => unreachable!(),
+        let forwarded_attrs = forwarded_attrs(variant);
+        let pattern = def.variant_pattern(variant);
+        if v.transparent {
+            let ty = &variant.fields.iter().next().unwrap().ty;
+            if mentions_type_param(ty, generics) {
+                display_bounds.push(parse_quote!(#ty: ::std::fmt::Display));
+            }
+            let binding = &field_bindings(&variant.fields)[0];
+            let body = quote_spanned! {ty.span()=>
+                ::std::fmt::Display::fmt(#binding, f)?;
+            };
+            display_impl_arms.push(quote! {
+                #forwarded_attrs
+                #pattern => {
+                    #body
+                }
+            });
+            details_impl_arms.push(quote! {
+                #forwarded_attrs
+                #pattern => {
+                    #body
+                }
+            });
+            continue;
+        }
+
+        let mut details = quote! {
+            f.write_str(#short_description)?;
         };
-        display_impl_arms.push(display_impl_arm);
+        if let Some(LongDescription { ref format_str, ref format_args }) = *long_description {
+            details.extend(quote! {
+                f.write_str(#separator)?;
+                write!(f, #format_str, #(#format_args,)*)?;
+            });
+        }
+        if let Some(idx) = *source_idx {
+            let ty = &variant.fields.iter().nth(idx).unwrap().ty;
+            if mentions_type_param(ty, generics) {
+                display_bounds.push(parse_quote!(#ty: ::std::error::Error + 'static));
+            }
+            let binding = &field_bindings(&variant.fields)[idx];
+            // The causes are written without the alternate flag, as their own chains are written
+            // here.
+            details.extend(quote! {
+                if chain {
+                    let mut source: Option<&(dyn ::std::error::Error + 'static)> = Some(#binding);
+                    while let Some(cause) = source {
+                        write!(f, "{}{}", #separator, cause)?;
+                        source = cause.source();
+                    }
+                }
+            });
+        }
+        details.extend(punctuation.clone());
+        details_impl_arms.push(quote! {
+            #forwarded_attrs
+            #pattern => {
+                #details
+            }
+        });
+
+        let short_pattern = def.variant_path(variant);
+        display_impl_arms.push(quote! {
+            #forwarded_attrs
+            #short_pattern { .. } => {
+                f.write_str(#short_description)?;
+                #punctuation
+            }
+        });
     }
 
-    let display_impl = quote_item!(cx, impl ::std::fmt::Display for $type_name {
-        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    let display_generics = with_bounds(generics, display_bounds);
+    let (impl_generics, _, where_clause) = display_generics.split_for_impl();
+    items.push(quote! {
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            /// Write the full description of this error, followed by the chain of errors which
+            /// caused it if `chain` is set.
             #[allow(unused)]
-            match *self {
-                $display_impl_arms
+            fn fmt_details(&self, f: &mut ::std::fmt::Formatter, chain: bool) -> ::std::fmt::Result {
+                #[allow(unused)]
+                match *self {
+                    #(#details_impl_arms)*
+                }
+                Ok(())
             }
-            Ok(())
         }
-    });
 
-    items.push(display_impl.unwrap());
+        impl #impl_generics ::std::fmt::Display for #type_name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                if f.alternate() {
+                    return self.fmt_details(f, true);
+                }
+                match *self {
+                    #(#display_impl_arms)*
+                }
+                Ok(())
+            }
+        }
+    });
 
     // Add Error impl
 
-    let mut description_impl_arms = Vec::new();
-    for v in &variants {
+    let mut description_impl = TokenStream::new();
+    if options.description {
+        let mut description_impl_arms = Vec::new();
+        for v in variants {
+            let VariantDef {
+                ref variant,
+                ref short_description,
+                ..
+            } = *v;
+            let path = def.variant_path(variant);
+
+            let forwarded_attrs = forwarded_attrs(variant);
+            description_impl_arms.push(quote! {
+                #forwarded_attrs
+                #path { .. } => #short_description,
+            });
+        }
+
+        description_impl = quote! {
+            fn description(&self) -> &str {
+                match *self {
+                    #(#description_impl_arms)*
+                }
+            }
+        };
+    }
+
+    let mut source_impl_arms = Vec::new();
+    let mut error_bounds: Vec<WherePredicate> = vec![
+        parse_quote!(Self: ::std::fmt::Debug + ::std::fmt::Display),
+    ];
+    for v in variants {
         let VariantDef {
             ref variant,
-            ref short_description,
+            ref source_idx,
             ..
         } = *v;
-        let Variant_ {
-            ref name,
-            ref data,
-            ..
-        } = variant.node;
-        
-        let short = dummy_spanned(ast::LitKind::Str(short_description.clone(), ast::StrStyle::Cooked));
-
-        let description_impl_arm = match *data {
-            VariantData::Unit(..) => {
-                quote_tokens!(cx, $type_name::$name => {
-                    $short
-                })
+        let forwarded_attrs = forwarded_attrs(variant);
+        let expr = match *source_idx {
+            // A transparent variant's field takes its place in the chain of errors, so the
+            // field's source is returned rather than the field itself.
+            _ if v.transparent => {
+                let ty = &variant.fields.iter().next().unwrap().ty;
+                if mentions_type_param(ty, generics) {
+                    error_bounds.push(parse_quote!(#ty: ::std::error::Error));
+                }
+                let binding = &field_bindings(&variant.fields)[0];
+                quote_spanned!(ty.span()=> ::std::error::Error::source(#binding))
             },
-            VariantData::Struct(..) => {
-                quote_tokens!(cx, $type_name::$name { .. } => {
-                    $short
-                })
+            None => quote!(None),
+            Some(idx) => {
+                let ty = &variant.fields.iter().nth(idx).unwrap().ty;
+                if mentions_type_param(ty, generics) {
+                    error_bounds.push(parse_quote!(#ty: ::std::error::Error + 'static));
+                } else {
+                    let assertion = assert_is_error(ty, generics);
+                    items.push(quote! {
+                        #forwarded_attrs
+                        #assertion
+                    });
+                }
+                let binding = &field_bindings(&variant.fields)[idx];
+                quote_spanned!(ty.span()=> Some(#binding))
             },
-            _ => unreachable!(),
         };
-        description_impl_arms.push(description_impl_arm);
+        let pattern = def.variant_pattern(variant);
+        source_impl_arms.push(quote! {
+            #forwarded_attrs
+            #pattern => #expr,
+        });
     }
 
-    let mut cause_impl_arms = Vec::new();
-    for v in &variants {
-        let VariantDef {
-            ref variant,
-            ref from_idx,
-            ..
-        } = *v;
-        let Variant_ {
-            ref name,
-            ref data,
-            ..
-        } = variant.node;
+    let error_generics = with_bounds(generics, error_bounds);
+    let (impl_generics, _, where_clause) = error_generics.split_for_impl();
+    items.push(quote! {
+        impl #impl_generics ::std::error::Error for #type_name #ty_generics #where_clause {
+            #description_impl
 
-        let cause_impl_arm = match *data {
-            VariantData::Unit(..) => {
-                quote_tokens!(cx, $type_name::$name => None,)
-            },
-            VariantData::Struct(ref members, ..) => {
-                let mut ms = Vec::new();
-                for member in members {
-                    let StructField {
-                        ref ident,
-                        ..
-                    } = *member;
-                    ms.extend(quote_tokens!(cx, ref $ident,));
-                }
-                let expr = match *from_idx {
-                    None => quote_expr!(cx, None),
-                    Some(idx) => {
-                        let StructField {
-                            ref ident,
-                            ..
-                        } = members[idx];
-                        quote_expr!(cx, Some($ident))
-                    },
-                };
-                quote_tokens!(cx, $type_name::$name { $ms } => $expr,)
+            fn source(&self) -> Option<&(dyn ::std::error::Error + 'static)> {
+                #[allow(unused)]
+                match *self {
+                    #(#source_impl_arms)*
+                }
+            }
+        }
+    });
+
+    // Add cause chain accessors
+
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    items.push(quote! {
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            /// An iterator over this error followed by the chain of errors which caused it.
+            pub fn chain(&self) -> impl ::std::iter::Iterator<Item = &(dyn ::std::error::Error + 'static)>
+            where
+                Self: ::std::error::Error + 'static,
+            {
+                ::std::iter::successors(Some(self as &(dyn ::std::error::Error + 'static)), |e| e.source())
+            }
+
+            /// The last error in the chain of errors which caused this one, or this error itself if
+            /// it has no source.
+            pub fn root_cause(&self) -> &(dyn ::std::error::Error + 'static)
+            where
+                Self: ::std::error::Error + 'static,
+            {
+                self.chain().last().unwrap()
+            }
+
+            /// The first error of type `__Cause` in the chain, starting with this error itself.
+            pub fn find_cause<__Cause: ::std::error::Error + 'static>(&self) -> Option<&__Cause>
+            where
+                Self: ::std::error::Error + 'static,
+            {
+                self.chain().find_map(|e| e.downcast_ref::<__Cause>())
+            }
+        }
+    });
+
+    // `report()` writes the full description, like `{:#}`, but with each cause on its own line.
+    let (impl_generics, _, where_clause) = display_generics.split_for_impl();
+    items.push(quote! {
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            /// A wrapper whose `Display` impl writes `Error: ` and this error's full description,
+            /// followed by an indented `Caused by: ` line for each error in the chain which caused
+            /// it.
+            pub fn report(&self) -> impl ::std::fmt::Display + '_
+            where
+                Self: ::std::error::Error + 'static,
+            {
+                struct Report<'a, H>(&'a (dyn ::std::error::Error + 'static), H)
+                where
+                    H: ::std::ops::Fn(&mut ::std::fmt::Formatter) -> ::std::fmt::Result;
+
+                impl<H> ::std::fmt::Display for Report<'_, H>
+                where
+                    H: ::std::ops::Fn(&mut ::std::fmt::Formatter) -> ::std::fmt::Result,
+                {
+                    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                        f.write_str("Error: ")?;
+                        (self.1)(f)?;
+                        let mut source = self.0.source();
+                        while let Some(cause) = source {
+                            write!(f, "\n    Caused by: {}", cause)?;
+                            source = cause.source();
+                        }
+                        Ok(())
+                    }
+                }
+
+                Report(self, move |f: &mut ::std::fmt::Formatter| self.fmt_details(f, false))
+            }
+        }
+    });
+
+    // Add error code accessors
+
+    if let Some(code_ty) = code_type(variants) {
+        let mut codes = Vec::new();
+        let mut code_impl_arms = Vec::new();
+        let mut try_from_impl_arms = Vec::new();
+        for v in variants {
+            let VariantDef {
+                ref variant,
+                ref code,
+                ..
+            } = *v;
+            let path = def.variant_path(variant);
+            let forwarded_attrs = forwarded_attrs(variant);
+
+            codes.push(quote!(#forwarded_attrs #code));
+            code_impl_arms.push(quote! {
+                #forwarded_attrs
+                #path { .. } => #code,
+            });
+            if let Fields::Unit = variant.fields {
+                try_from_impl_arms.push(quote! {
+                    #forwarded_attrs
+                    #code => Ok(#path),
+                });
+            }
+        }
+
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// The error codes of all of this type's variants, in the order they are defined.
+                pub const CODES: &'static [#code_ty] = &[#(#codes,)*];
+
+                /// The error code of this error.
+                pub fn code(&self) -> #code_ty {
+                    match *self {
+                        #(#code_impl_arms)*
+                    }
+                }
+            }
+        });
+
+        // String codes are converted from a borrowed `&str`, which needs its own lifetime.
+        let mut try_from_generics = generics.clone();
+        let code_ty = match code_ty {
+            Type::Reference(..) => {
+                try_from_generics.params.insert(0, parse_quote!('__code));
+                quote!(&'__code str)
             },
-            _ => unreachable!(),
+            code_ty => quote!(#code_ty),
         };
-        cause_impl_arms.push(cause_impl_arm);
+        let (impl_generics, _, where_clause) = try_from_generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics ::std::convert::TryFrom<#code_ty> for #type_name #ty_generics #where_clause {
+                type Error = #code_ty;
+
+                /// Create the unit-like variant with the given error code. The code is returned
+                /// back if there is no such variant.
+                fn try_from(code: #code_ty) -> ::std::result::Result<Self, #code_ty> {
+                    match code {
+                        #(#try_from_impl_arms)*
+                        _ => Err(code),
+                    }
+                }
+            }
+        });
     }
 
-    let error_impl = quote_item!(cx, impl ::std::error::Error for $type_name {
-        fn description(&self) -> &str {
-            match *self {
-                $description_impl_arms
+    // Add the kind enum
+
+    if options.kind.is_some() {
+        let kind_name = format_ident!("{}Kind", type_name);
+        let mut kind_variants = Vec::new();
+        let mut kind_impl_arms = Vec::new();
+        let mut kinds = Vec::new();
+        for v in variants {
+            let VariantDef {
+                ref variant,
+                ref short_description,
+                ..
+            } = *v;
+            let name = &variant.ident;
+            let path = def.variant_path(variant);
+            let forwarded_attrs = forwarded_attrs(variant);
+
+            let comment = format!(" {}.", short_description.value());
+            let kind_attrs = variant
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("cfg") || attr.path().is_ident("deprecated"));
+            kind_variants.push(quote! {
+                #[doc = #comment]
+                #(#kind_attrs)*
+                #name,
+            });
+            kind_impl_arms.push(quote! {
+                #forwarded_attrs
+                #path { .. } => #kind_name::#name,
+            });
+            kinds.push(quote!(#forwarded_attrs #kind_name::#name));
+        }
+
+        let kind_doc = format!(" The kinds of [`{}`], without their fields.", type_name);
+        items.push(quote! {
+            #[doc = #kind_doc]
+            #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+            #vis enum #kind_name {
+                #(#kind_variants)*
+            }
+
+            impl #kind_name {
+                /// Every kind, in the order they are defined.
+                #[allow(deprecated)]
+                pub const ALL: &'static [#kind_name] = &[#(#kinds,)*];
+            }
+        });
+
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// The kind of this error.
+                pub fn kind(&self) -> #kind_name {
+                    match *self {
+                        #(#kind_impl_arms)*
+                    }
+                }
+            }
+
+            impl #impl_generics ::std::cmp::PartialEq<#kind_name> for #type_name #ty_generics #where_clause {
+                fn eq(&self, kind: &#kind_name) -> bool {
+                    self.kind() == *kind
+                }
             }
+
+            impl #impl_generics ::std::cmp::PartialEq<#type_name #ty_generics> for #kind_name #where_clause {
+                fn eq(&self, error: &#type_name #ty_generics) -> bool {
+                    *self == error.kind()
+                }
+            }
+        });
+    }
+
+    // Add exit code accessors
+
+    if options.exit_code.is_some() || variants.iter().any(|v| v.exit_code.is_some()) {
+        let default_exit_code = match options.exit_code {
+            Some(ref exit_code) => quote!(#exit_code),
+            None => quote!(1),
+        };
+        let mut exit_code_impl_arms = Vec::new();
+        for v in variants {
+            let VariantDef {
+                ref variant,
+                ref exit_code,
+                ..
+            } = *v;
+            let path = def.variant_path(variant);
+            let exit_code = match *exit_code {
+                Some(ref exit_code) => quote!(#exit_code),
+                None => default_exit_code.clone(),
+            };
+
+            let forwarded_attrs = forwarded_attrs(variant);
+            exit_code_impl_arms.push(quote! {
+                #forwarded_attrs
+                #path { .. } => #exit_code,
+            });
         }
 
-        fn cause(&self) -> Option<&::std::error::Error> {
-            #[allow(unused)]
-            match *self {
-                $cause_impl_arms
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// The process exit code for this error, which is between 1 and 255 so that it can
+                /// be given to `ExitCode::from`.
+                pub fn exit_code(&self) -> u8 {
+                    match *self {
+                        #(#exit_code_impl_arms)*
+                    }
+                }
+            }
+        });
+
+        let (impl_generics, _, where_clause) = display_generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// Print this error, followed by the chain of errors which caused it, to stderr and
+                /// return the process exit code for it.
+                pub fn report_exit(&self) -> ::std::process::ExitCode
+                where
+                    Self: ::std::error::Error + 'static,
+                {
+                    eprintln!("{}", self.report());
+                    ::std::process::ExitCode::from(self.exit_code())
+                }
             }
+        });
+
+        // `main` can't return a `Result` with the error directly, as std's `Termination` impl for
+        // `Result` prints the `Debug` text and always exits with 1.
+        let exit_name = format_ident!("{}Exit", type_name);
+        let exit_doc = format!(
+            " The result of a `main` function which reports a [`{}`] and exits with its exit code.",
+            type_name,
+        );
+        let where_clause = &generics.where_clause;
+        let (impl_generics, _, _) = generics.split_for_impl();
+        let termination_generics = with_bounds(
+            &display_generics,
+            vec![parse_quote!(#type_name #ty_generics: ::std::error::Error + 'static)],
+        );
+        let (termination_impl_generics, _, termination_where_clause) = termination_generics.split_for_impl();
+        items.push(quote! {
+            #[doc = #exit_doc]
+            #vis struct #exit_name #generics(pub ::std::result::Result<(), #type_name #ty_generics>) #where_clause;
+
+            impl #impl_generics ::std::convert::From<::std::result::Result<(), #type_name #ty_generics>>
+                for #exit_name #ty_generics #where_clause
+            {
+                fn from(result: ::std::result::Result<(), #type_name #ty_generics>) -> Self {
+                    #exit_name(result)
+                }
+            }
+
+            impl #termination_impl_generics ::std::process::Termination for #exit_name #ty_generics
+                #termination_where_clause
+            {
+                fn report(self) -> ::std::process::ExitCode {
+                    match self.0 {
+                        Ok(()) => ::std::process::ExitCode::SUCCESS,
+                        Err(e) => e.report_exit(),
+                    }
+                }
+            }
+        });
+    }
+
+    // Add HTTP status accessors
+
+    if options.status.is_some() || variants.iter().any(|v| v.status.is_some()) {
+        let default_status = match options.status {
+            Some(ref status) => quote!(#status),
+            None => quote!(500),
+        };
+        let mut status_impl_arms = Vec::new();
+        let mut problem_impl_arms = Vec::new();
+        let mut problem_bounds: Vec<WherePredicate> = Vec::new();
+        for v in variants {
+            let VariantDef {
+                ref variant,
+                ref short_description,
+                ref code,
+                ref status,
+                ref extensions,
+                ref long_description,
+                ..
+            } = *v;
+            let path = def.variant_path(variant);
+            let forwarded_attrs = forwarded_attrs(variant);
+
+            let status = match *status {
+                Some(ref status) => quote!(#status),
+                None => default_status.clone(),
+            };
+            status_impl_arms.push(quote! {
+                #forwarded_attrs
+                #path { .. } => #status,
+            });
+
+            let problem_type = match *code {
+                Some(ref code) => quote!(::std::string::ToString::to_string(&#code)),
+                None => {
+                    let name = variant.ident.to_string();
+                    quote!(::std::string::String::from(#name))
+                },
+            };
+            let detail = match *long_description {
+                Some(LongDescription { ref format_str, ref format_args }) => {
+                    quote!(Some(format!(#format_str, #(#format_args,)*)))
+                },
+                None => quote!(None),
+            };
+            let bindings = field_bindings(&variant.fields);
+            let extensions = extensions.iter().map(|&idx| {
+                let ty = &variant.fields.iter().nth(idx).unwrap().ty;
+                if mentions_type_param(ty, generics) {
+                    problem_bounds.push(parse_quote!(#ty: ::std::fmt::Display));
+                }
+                let binding = &bindings[idx];
+                let name = binding.to_string();
+                quote!((#name, ::std::string::ToString::to_string(#binding)))
+            });
+            let extensions = quote!(vec![#(#extensions,)*]);
+            let pattern = def.variant_pattern(variant);
+            problem_impl_arms.push(quote! {
+                #forwarded_attrs
+                #pattern => (#problem_type, #short_description, #detail, #extensions),
+            });
         }
-    });
 
-    items.push(error_impl.unwrap());
+        let problem_name = format_ident!("{}ProblemDetails", type_name);
+        let problem_doc = format!(" An RFC 7807 problem details object describing a [`{}`].", type_name);
+        items.push(quote! {
+            #[doc = #problem_doc]
+            #[derive(Clone, Debug, PartialEq, Eq)]
+            #vis struct #problem_name {
+                /// Identifies the kind of problem. This is the error code if the error has one,
+                /// and otherwise the variant name.
+                pub r#type: ::std::string::String,
+                /// The short description of the error.
+                pub title: &'static str,
+                /// The HTTP status code of the error.
+                pub status: u16,
+                /// The long description of the error, if it has one.
+                pub detail: ::std::option::Option<::std::string::String>,
+                /// The error's fields marked `#[extension]`, formatted with `Display`.
+                pub extensions: ::std::vec::Vec<(&'static str, ::std::string::String)>,
+            }
+
+            impl #problem_name {
+                /// Render this object as an `application/problem+json` document.
+                pub fn to_json(&self) -> ::std::string::String {
+                    fn push_json_str(json: &mut ::std::string::String, s: &str) {
+                        json.push('"');
+                        for c in s.chars() {
+                            match c {
+                                '"' => json.push_str("\\\""),
+                                '\\' => json.push_str("\\\\"),
+                                '\n' => json.push_str("\\n"),
+                                '\r' => json.push_str("\\r"),
+                                '\t' => json.push_str("\\t"),
+                                c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+                                c => json.push(c),
+                            }
+                        }
+                        json.push('"');
+                    }
+
+                    let mut json = ::std::string::String::from("{\"type\":");
+                    push_json_str(&mut json, &self.r#type);
+                    json.push_str(",\"title\":");
+                    push_json_str(&mut json, self.title);
+                    json.push_str(&format!(",\"status\":{}", self.status));
+                    if let Some(ref detail) = self.detail {
+                        json.push_str(",\"detail\":");
+                        push_json_str(&mut json, detail);
+                    }
+                    for &(name, ref value) in &self.extensions {
+                        json.push(',');
+                        push_json_str(&mut json, name);
+                        json.push(':');
+                        push_json_str(&mut json, value);
+                    }
+                    json.push('}');
+                    json
+                }
+            }
+        });
+
+        let problem_where_clause = if problem_bounds.is_empty() {
+            TokenStream::new()
+        } else {
+            quote!(where #(#problem_bounds,)*)
+        };
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        items.push(quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// The HTTP status code for this error.
+                pub fn status_code(&self) -> u16 {
+                    match *self {
+                        #(#status_impl_arms)*
+                    }
+                }
+
+                /// Describe this error as an RFC 7807 problem details object.
+                pub fn to_problem_details(&self) -> #problem_name #problem_where_clause {
+                    #[allow(unused)]
+                    let (r#type, title, detail, extensions) = match *self {
+                        #(#problem_impl_arms)*
+                    };
+                    #problem_name {
+                        r#type,
+                        title,
+                        status: self.status_code(),
+                        detail,
+                        extensions,
+                    }
+                }
+            }
+        });
+    }
+
+    // Add the error reference
+
+    items.push(reference::expand_reference(def));
+
+    // Add localized messages
+
+    match catalog::expand_localized(def) {
+        Ok(localized) => items.push(localized),
+        Err(e) => items.push(e.to_compile_error()),
+    }
+
+    // Add constructors and accessors
+
+    match accessors::expand_accessors(def) {
+        Ok(accessors) => items.push(accessors),
+        Err(e) => items.push(e.to_compile_error()),
+    }
+
+    // Add context extension traits
+
+    match context::expand_context(def) {
+        Ok(context) => items.push(context),
+        Err(e) => items.push(e.to_compile_error()),
+    }
+
+    // Add serde impls
+
+    #[cfg(feature = "serde")]
+    if options.serde {
+        items.push(serde::expand_serde_impls(def));
+    }
 
     // Add `From` impls
-    for v in &variants {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    for v in variants {
         let VariantDef {
             ref variant,
-            ref from_idx,
+            ref from_with,
+            ref defaults,
             ..
         } = *v;
-        let Variant_ {
-            ref name,
-            ref data,
-            ..
-        } = variant.node;
+        let fields = &variant.fields;
 
-        if let Some(idx) = *from_idx {
-            let members = match *data {
-                VariantData::Struct(ref members, ..) => members,
-                _ => unreachable!(),
+        if let Some(idx) = v.converted_member_idx() {
+            let path = def.variant_path(variant);
+            let ty = v.converted_type(idx);
+            let converted = match *from_with {
+                Some(FromWith { ref function, .. }) => quote!((#function)(val)),
+                None => quote!(val),
             };
-            if members.len() != 1 {
-                continue;
-            }
-            let StructField {
-                ref ty,
-                ref ident,
-                ..
-            } = members[idx];
-            let from_impl = quote_item!(cx, impl ::std::convert::From<$ty> for $type_name {
-                fn from(val: $ty) -> $type_name {
-                    $type_name::$name { $ident: val }
+            let values = defaults.iter().enumerate().map(|(i, default)| match *default {
+                _ if i == idx => converted.clone(),
+                Some(ref default) => quote!(#default),
+                None => unreachable!(),
+            });
+            let value = match *fields {
+                Fields::Named(..) => {
+                    let idents = fields.iter().map(|field| &field.ident);
+                    quote!(#path { #(#idents: #values,)* })
+                },
+                Fields::Unnamed(..) => quote!(#path(#(#values,)*)),
+                Fields::Unit => unreachable!(),
+            };
+            let forwarded_attrs = forwarded_attrs(variant);
+            items.push(quote! {
+                #forwarded_attrs
+                impl #impl_generics ::std::convert::From<#ty> for #type_name #ty_generics #where_clause {
+                    fn from(val: #ty) -> Self {
+                        #value
+                    }
                 }
             });
-            items.push(from_impl.unwrap());
         }
     }
 
-    MacEager::items((SmallVector::many(items)))
+    quote!(#(#items)*)
+}
+
+/// The identifiers that a variant's fields are bound to in the generated match arms. Named fields
+/// are bound to their own names and tuple fields to `_0`, `_1`, etc.
+fn field_bindings(fields: &Fields) -> Vec<Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match field.ident {
+            Some(ref ident) => ident.clone(),
+            None => format_ident!("_{}", i),
+        })
+        .collect()
+}
+
+/// The attributes of a variant which need to be repeated on every match arm or impl that refers
+/// to it. `#[cfg]` attributes are copied so that code for a disabled variant is removed along
+/// with the variant, and uses of a `#[deprecated]` variant are allowed.
+fn forwarded_attrs(variant: &Variant) -> TokenStream {
+    let mut tokens = TokenStream::new();
+    for attr in &variant.attrs {
+        if attr.path().is_ident("cfg") {
+            attr.to_tokens(&mut tokens);
+        } else if attr.path().is_ident("deprecated") {
+            tokens.extend(quote!(#[allow(deprecated)]));
+        }
+    }
+    tokens
 }
 
-#[plugin_registrar]
-pub fn plugin_registrar(reg: &mut Registry) {
-    reg.register_syntax_extension(
-        Symbol::intern("error_def"),
-        SyntaxExtension::IdentTT(Box::new(expand_error_def), None, false)
-    );
+impl ErrorDef {
+    /// The path to `variant`, which is just the type's name if it is a struct.
+    fn variant_path(&self, variant: &Variant) -> TokenStream {
+        let type_name = &self.type_name;
+        let name = &variant.ident;
+        match self.kind {
+            TypeKind::Enum => quote!(#type_name::#name),
+            TypeKind::Struct => quote!(#type_name),
+        }
+    }
+
+    /// A pattern which matches `variant` and binds each of its fields by reference.
+    fn variant_pattern(&self, variant: &Variant) -> TokenStream {
+        let path = self.variant_path(variant);
+        let bindings = field_bindings(&variant.fields);
+        match variant.fields {
+            Fields::Unit => quote!(#path),
+            Fields::Named(..) => quote!(#path { #(ref #bindings,)* }),
+            Fields::Unnamed(..) => quote!(#path(#(ref #bindings,)*)),
+        }
+    }
 }
 
+/// Generate an item which fails to compile if `ty` is not a `'static` `Error`, with the error
+/// pointing at `ty` rather than at the whole macro invocation.
+fn assert_is_error(ty: &Type, generics: &Generics) -> TokenStream {
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let assertion = quote_spanned! {ty.span()=>
+        assert_is_error::<#ty>();
+    };
+    quote! {
+        const _: () = {
+            #[diagnostic::on_unimplemented(
+                message = "`{Self}` cannot be used as an error source because it does not implement `std::error::Error`",
+                note = "#[from] and #[source] fields must be `'static` types which implement `std::error::Error`"
+            )]
+            trait IsError {}
+            impl<T: ?Sized + ::std::error::Error + 'static> IsError for T {}
+            fn assert_is_error<T: ?Sized + IsError>() {}
+            #[allow(dead_code)]
+            fn check #impl_generics () #where_clause {
+                #assertion
+            }
+        };
+    }
+}
+
+/// Copy `generics`, adding `bounds` to its where clause.
+fn with_bounds(generics: &Generics, bounds: Vec<WherePredicate>) -> Generics {
+    let mut generics = generics.clone();
+    generics.make_where_clause().predicates.extend(bounds);
+    generics
+}
+
+/// Check whether `ty` refers to any of the type parameters in `generics`. Bounds are only added
+/// for field types that do, since the bounds on any other type are either trivially true or
+/// would already cause a compile error.
+fn mentions_type_param(ty: &Type, generics: &Generics) -> bool {
+    fn walk(tokens: TokenStream, generics: &Generics) -> bool {
+        tokens.into_iter().any(|tt| match tt {
+            TokenTree::Ident(ident) => generics.type_params().any(|param| param.ident == ident),
+            TokenTree::Group(group) => walk(group.stream(), generics),
+            _ => false,
+        })
+    }
+
+    walk(ty.to_token_stream(), generics)
+}
+
+/// Define an error enum or struct along with its `Debug`, `Display`, `Error` and `From` impls.
+///
+/// See the crate's README for a description of the syntax.
+#[proc_macro]
+pub fn error_def(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let def = parse_macro_input!(input as ErrorDef);
+    expand_error_def(def).into()
+}
+
+/// Generate the same impls as `error_def!` for a hand-written enum.
+///
+/// Each variant takes an `#[error_def(short = "...", long("format string", args...), code = ...)]`
+/// attribute, where `long` and `code` are optional. Variants may also be marked `#[exit_code = N]`,
+/// `#[status = N]` or `#[transparent]`, and fields may be marked `#[from]`, `#[source]`,
+/// `#[default]` or `#[extension]`, as in `error_def!`. Options for the whole type, such as
+/// `#[error_def(description)]`, go on the enum itself.
+#[proc_macro_derive(ErrorDef, attributes(error_def, from, source, default, extension, exit_code, status, transparent))]
+pub fn derive_error_def(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive::parse_derive_input(input) {
+        Ok(def) => expand_impls(&def).into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
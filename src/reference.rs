@@ -0,0 +1,252 @@
+//! The error reference, generated with `#[error_def(reference)]`: a JSON and a Markdown
+//! description of an error type which are built at compile time, so that documentation can be
+//! generated from them rather than written by hand.
+
+use std::fmt::Write;
+
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{Expr, Lit};
+
+use crate::{ErrorDef, LongDescription, TypeKind, VariantDef};
+
+/// The parts of a variant which are described in the reference.
+struct VariantReference {
+    name: String,
+    fields: Vec<(String, String)>,
+    short: String,
+    long: Option<(String, Vec<String>)>,
+    code: Option<Lit>,
+    from: Option<String>,
+    /// The predicate of the variant's `#[cfg]` attributes, if it has any.
+    cfg: Option<String>,
+}
+
+/// Generate the `REFERENCE_JSON` and `REFERENCE_MARKDOWN` constants.
+pub fn expand_reference(def: &ErrorDef) -> TokenStream {
+    let ErrorDef {
+        ref options,
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+    if !options.reference {
+        return TokenStream::new();
+    }
+
+    let variants: Vec<VariantReference> = variants.iter().map(variant_reference).collect();
+    let type_name_str = type_name.to_string();
+    let json = to_json(&type_name_str, &variants);
+    let markdown = to_markdown(&type_name_str, def.kind, &variants);
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #type_name #ty_generics #where_clause {
+            /// A JSON description of this type's variants, for generating documentation.
+            pub const REFERENCE_JSON: &'static str = #json;
+
+            /// A Markdown description of this type's variants, for generating documentation.
+            pub const REFERENCE_MARKDOWN: &'static str = #markdown;
+        }
+    }
+}
+
+fn variant_reference(v: &VariantDef) -> VariantReference {
+    let VariantDef {
+        ref variant,
+        ref short_description,
+        ref code,
+        ref long_description,
+        ..
+    } = *v;
+
+    let fields = variant
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let name = match field.ident {
+                Some(ref ident) => ident.to_string(),
+                None => i.to_string(),
+            };
+            (name, tokens_string(&field.ty))
+        })
+        .collect();
+
+    // Arguments which just capture a field of the same name are already described by the format
+    // string itself.
+    let long = long_description.as_ref().map(|LongDescription { format_str, format_args }| {
+        let args = format_args
+            .iter()
+            .filter(|arg| match **arg {
+                Expr::Assign(ref assign) => {
+                    assign.left.to_token_stream().to_string() != assign.right.to_token_stream().to_string()
+                },
+                _ => true,
+            })
+            .map(tokens_string)
+            .collect();
+        (format_str.value(), args)
+    });
+
+    // A variant with several `#[cfg]` attributes is only present when all of them hold.
+    let cfgs: Vec<TokenStream> = variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("cfg"))
+        .filter_map(|attr| attr.meta.require_list().ok())
+        .map(|list| list.tokens.clone())
+        .collect();
+    let cfg = match *cfgs {
+        [] => None,
+        [ref cfg] => Some(tokens_string(cfg)),
+        _ => Some(tokens_string(quote!(all(#(#cfgs),*)))),
+    };
+
+    VariantReference {
+        name: variant.ident.to_string(),
+        fields,
+        short: short_description.value(),
+        long,
+        code: code.clone(),
+        from: v.converted_member_idx().map(|idx| tokens_string(v.converted_type(idx))),
+        cfg,
+    }
+}
+
+/// Render tokens, such as a type, the way they would usually be written.
+fn tokens_string<T: ToTokens>(tokens: T) -> String {
+    let mut s = tokens.to_token_stream().to_string();
+    for (from, to) in [
+        (" :: ", "::"),
+        (":: ", "::"),
+        (" ::", "::"),
+        (" < ", "<"),
+        (" <", "<"),
+        ("< ", "<"),
+        (" >", ">"),
+        (" ,", ","),
+        ("& ", "&"),
+        ("' ", "'"),
+        ("( ", "("),
+        (" )", ")"),
+        ("[ ", "["),
+        (" ]", "]"),
+        (" ;", ";"),
+        (" . ", "."),
+        (" .", "."),
+    ] {
+        s = s.replace(from, to);
+    }
+    s
+}
+
+fn code_json(code: &Lit) -> String {
+    match *code {
+        Lit::Str(ref code) => json_string(&code.value()),
+        Lit::Int(ref code) => String::from(code.base10_digits()),
+        _ => unreachable!(),
+    }
+}
+
+fn code_markdown(code: &Lit) -> String {
+    match *code {
+        Lit::Str(ref code) => code.value(),
+        Lit::Int(ref code) => String::from(code.base10_digits()),
+        _ => unreachable!(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_option(value: Option<String>) -> String {
+    value.unwrap_or_else(|| String::from("null"))
+}
+
+fn to_json(type_name: &str, variants: &[VariantReference]) -> String {
+    let variants: Vec<String> = variants
+        .iter()
+        .map(|v| {
+            let fields: Vec<String> = v
+                .fields
+                .iter()
+                .map(|(name, ty)| format!("{{\"name\":{},\"type\":{}}}", json_string(name), json_string(ty)))
+                .collect();
+            let (long, long_args) = match v.long {
+                Some((ref format_str, ref args)) => {
+                    let args: Vec<String> = args.iter().map(|arg| json_string(arg)).collect();
+                    (Some(json_string(format_str)), args)
+                },
+                None => (None, Vec::new()),
+            };
+            format!(
+                "{{\"name\":{},\"fields\":[{}],\"short\":{},\"long\":{},\"long_args\":[{}],\"code\":{},\"from\":{},\"cfg\":{}}}",
+                json_string(&v.name),
+                fields.join(","),
+                json_string(&v.short),
+                json_option(long),
+                long_args.join(","),
+                json_option(v.code.as_ref().map(code_json)),
+                json_option(v.from.as_deref().map(json_string)),
+                json_option(v.cfg.as_deref().map(json_string)),
+            )
+        })
+        .collect();
+    format!("{{\"type\":{},\"variants\":[{}]}}", json_string(type_name), variants.join(","))
+}
+
+fn to_markdown(type_name: &str, kind: TypeKind, variants: &[VariantReference]) -> String {
+    let mut markdown = format!("## `{}`\n", type_name);
+    for v in variants {
+        // A struct's only variant is described directly under the type's heading.
+        if kind == TypeKind::Enum {
+            write!(markdown, "\n### `{}`\n", v.name).unwrap();
+        }
+        write!(markdown, "\n{}\n", v.short).unwrap();
+        let mut items = Vec::new();
+        if let Some(ref code) = v.code {
+            items.push(format!("Code: `{}`", code_markdown(code)));
+        }
+        if !v.fields.is_empty() {
+            let fields: Vec<String> = v.fields.iter().map(|(name, ty)| format!("`{}: {}`", name, ty)).collect();
+            items.push(format!("Fields: {}", fields.join(", ")));
+        }
+        if let Some((ref format_str, ref args)) = v.long {
+            let mut item = format!("Details: `{}`", format_str);
+            if !args.is_empty() {
+                let args: Vec<String> = args.iter().map(|arg| format!("`{}`", arg)).collect();
+                write!(item, " with {}", args.join(", ")).unwrap();
+            }
+            items.push(item);
+        }
+        if let Some(ref from) = v.from {
+            items.push(format!("Converted from: `{}`", from));
+        }
+        if let Some(ref cfg) = v.cfg {
+            items.push(format!("Only when: `cfg({})`", cfg));
+        }
+        if !items.is_empty() {
+            markdown.push('\n');
+            for item in items {
+                writeln!(markdown, "- {}", item).unwrap();
+            }
+        }
+    }
+    markdown
+}
@@ -0,0 +1,268 @@
+//! `Serialize` and `Deserialize` impls, generated with `#[error_def(serde)]` when the `serde`
+//! feature is enabled.
+//!
+//! Errors are serialized as an object holding the variant name, the short description, the full
+//! `{:#}` message, the variant's fields and the `Display` messages of the chain of errors which
+//! caused it:
+//!
+//! ```json
+//! {"variant":"NotFound","short":"Not found","message":"Not found. no such user","fields":{"name":"user"},"causes":[]}
+//! ```
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_quote, Fields, Variant, WherePredicate};
+
+use crate::{field_bindings, forwarded_attrs, mentions_type_param, with_bounds, ErrorDef,
+            VariantDef};
+
+pub fn expand_serde_impls(def: &ErrorDef) -> TokenStream {
+    let mut items = vec![expand_serialize(def)];
+    // Sources can't be deserialized, and the bounds on the fields of a #[cfg] variant can't be
+    // disabled along with it.
+    let deserializable = def
+        .variants
+        .iter()
+        .all(|v| v.source_idx.is_none() && !v.variant.attrs.iter().any(|attr| attr.path().is_ident("cfg")));
+    if deserializable {
+        items.push(expand_deserialize(def));
+    }
+    quote!(#(#items)*)
+}
+
+/// The keys that a variant's fields are serialized under: the field's name, or its position in a
+/// tuple variant.
+fn field_keys(variant: &Variant) -> Vec<String> {
+    variant
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match field.ident {
+            Some(ref ident) => ident.to_string(),
+            None => i.to_string(),
+        })
+        .collect()
+}
+
+fn expand_serialize(def: &ErrorDef) -> TokenStream {
+    let ErrorDef {
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+
+    let mut variant_impl_arms = Vec::new();
+    let mut fields_impl_arms = Vec::new();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    let mut bounds: Vec<WherePredicate> = vec![parse_quote!(#type_name #ty_generics: ::std::error::Error)];
+    for v in variants {
+        let VariantDef {
+            ref variant,
+            ref short_description,
+            ref source_idx,
+            ..
+        } = *v;
+        let name = &variant.ident;
+        let name_str = name.to_string();
+        let has_fields = !variant.fields.is_empty();
+        let path = def.variant_path(variant);
+        let forwarded_attrs = forwarded_attrs(variant);
+
+        variant_impl_arms.push(quote! {
+            #forwarded_attrs
+            #path { .. } => (#name_str, #short_description, #has_fields),
+        });
+
+        // A source is serialized as part of the cause chain rather than as a field.
+        let bindings = field_bindings(&variant.fields);
+        let keys = field_keys(variant);
+        let mut fields = Vec::new();
+        for (i, member) in variant.fields.iter().enumerate() {
+            if *source_idx == Some(i) {
+                continue;
+            }
+            let ty = &member.ty;
+            if mentions_type_param(ty, generics) {
+                bounds.push(parse_quote!(#ty: ::serde::Serialize));
+            }
+            let binding = &bindings[i];
+            let key = &keys[i];
+            fields.push(quote! {
+                (&SerializeIfPossible(#binding)).serialize_entry(&mut map, #key)?;
+            });
+        }
+        let pattern = def.variant_pattern(variant);
+        fields_impl_arms.push(quote! {
+            #forwarded_attrs
+            #pattern => {
+                #(#fields)*
+            }
+        });
+    }
+
+    let serialize_generics = with_bounds(generics, bounds);
+    let (impl_generics, _, where_clause) = serialize_generics.split_for_impl();
+    let type_name_str = type_name.to_string();
+    quote! {
+        const _: () = {
+            use ::serde::ser::{SerializeMap, SerializeStruct};
+
+            // Fields whose types implement `Serialize` are written to the map. Method resolution
+            // only falls back to `SkipField`, which is implemented for a reference to the wrapper,
+            // when `SerializeField` is not implemented.
+            struct SerializeIfPossible<'a, T: ?Sized>(&'a T);
+
+            trait SerializeField {
+                fn serialize_entry<M: SerializeMap>(&self, map: &mut M, key: &'static str) -> ::std::result::Result<(), M::Error>;
+            }
+
+            impl<'a, T: ?Sized + ::serde::Serialize> SerializeField for SerializeIfPossible<'a, T> {
+                fn serialize_entry<M: SerializeMap>(&self, map: &mut M, key: &'static str) -> ::std::result::Result<(), M::Error> {
+                    map.serialize_entry(key, self.0)
+                }
+            }
+
+            trait SkipField {
+                fn serialize_entry<M: SerializeMap>(&self, _map: &mut M, _key: &'static str) -> ::std::result::Result<(), M::Error> {
+                    Ok(())
+                }
+            }
+
+            impl<'a, T: ?Sized> SkipField for &SerializeIfPossible<'a, T> {}
+
+            struct VariantFields<'a, E: ?Sized>(&'a E);
+
+            impl #impl_generics ::serde::Serialize for VariantFields<'_, #type_name #ty_generics> #where_clause {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+                    let mut map = serializer.serialize_map(None)?;
+                    #[allow(unused)]
+                    match *self.0 {
+                        #(#fields_impl_arms)*
+                    }
+                    map.end()
+                }
+            }
+
+            // The chain of errors starting at an error's source.
+            struct Causes<'a>(Option<&'a (dyn ::std::error::Error + 'static)>);
+
+            impl ::serde::Serialize for Causes<'_> {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+                    let mut causes = ::std::vec::Vec::new();
+                    let mut source = self.0;
+                    while let Some(cause) = source {
+                        causes.push(cause.to_string());
+                        source = cause.source();
+                    }
+                    serializer.collect_seq(causes)
+                }
+            }
+
+            impl #impl_generics ::serde::Serialize for #type_name #ty_generics #where_clause {
+                fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+                    let (variant, short, has_fields) = match *self {
+                        #(#variant_impl_arms)*
+                    };
+                    let len = if has_fields { 5 } else { 4 };
+                    let mut state = serializer.serialize_struct(#type_name_str, len)?;
+                    state.serialize_field("variant", variant)?;
+                    state.serialize_field("short", short)?;
+                    state.serialize_field("message", &format!("{:#}", self))?;
+                    if has_fields {
+                        state.serialize_field("fields", &VariantFields(self))?;
+                    } else {
+                        state.skip_field("fields")?;
+                    }
+                    state.serialize_field("causes", &Causes(::std::error::Error::source(self)))?;
+                    state.end()
+                }
+            }
+        };
+    }
+}
+
+fn expand_deserialize(def: &ErrorDef) -> TokenStream {
+    let ErrorDef {
+        ref type_name,
+        ref generics,
+        ref variants,
+        ..
+    } = *def;
+
+    // The fields are deserialized through a mirror of the enum, with a type parameter standing in
+    // for the type of each field, so that `serde_derive` can do the work. Bounding the field types
+    // on the `'de` lifetime means that the impl simply doesn't apply when some field can't be
+    // deserialized.
+    let mut params = Vec::new();
+    let mut field_types = Vec::new();
+    let mut bounds: Vec<WherePredicate> = Vec::new();
+    let mut repr_variants = Vec::new();
+    let mut from_repr_arms = Vec::new();
+    for v in variants {
+        let variant = &v.variant;
+        let name = &variant.ident;
+        let bindings = field_bindings(&variant.fields);
+        let keys = field_keys(variant);
+
+        let mut repr_fields = Vec::new();
+        for ((member, binding), key) in variant.fields.iter().zip(&bindings).zip(&keys) {
+            let param = format_ident!("F{}", params.len());
+            let ty = &member.ty;
+            bounds.push(parse_quote!(#ty: ::serde::Deserialize<'__de>));
+            repr_fields.push(quote!(#[serde(rename = #key)] #binding: #param));
+            params.push(param);
+            field_types.push(ty);
+        }
+
+        let path = def.variant_path(variant);
+        let value = match variant.fields {
+            Fields::Unit => quote!(#path),
+            Fields::Named(..) => quote!(#path { #(#bindings,)* }),
+            Fields::Unnamed(..) => quote!(#path(#(#bindings,)*)),
+        };
+        let repr_variant = match variant.fields {
+            Fields::Unit => quote!(#name),
+            _ => quote!(#name { #(#repr_fields,)* }),
+        };
+        repr_variants.push(repr_variant);
+        from_repr_arms.push(quote! {
+            Repr::#name { #(#bindings,)* } => #value,
+        });
+    }
+
+    // Every parameter must be used, even those of variants which are disabled.
+    let phantom = if params.is_empty() {
+        TokenStream::new()
+    } else {
+        from_repr_arms.push(quote!(Repr::Phantom(..) => unreachable!(),));
+        quote! {
+            #[serde(skip)]
+            Phantom(::std::marker::PhantomData<(#(#params,)*)>),
+        }
+    };
+
+    let mut deserialize_generics = with_bounds(generics, bounds);
+    deserialize_generics.params.insert(0, parse_quote!('__de));
+    let (impl_generics, _, where_clause) = deserialize_generics.split_for_impl();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    quote! {
+        const _: () = {
+            #[derive(::serde::Deserialize)]
+            #[serde(tag = "variant", content = "fields")]
+            enum Repr<#(#params,)*> {
+                #(#repr_variants,)*
+                #phantom
+            }
+
+            impl #impl_generics ::serde::Deserialize<'__de> for #type_name #ty_generics #where_clause {
+                fn deserialize<D: ::serde::Deserializer<'__de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+                    let repr = <Repr<#(#field_types,)*> as ::serde::Deserialize>::deserialize(deserializer)?;
+                    Ok(match repr {
+                        #(#from_repr_arms)*
+                    })
+                }
+            }
+        };
+    }
+}
@@ -0,0 +1,7 @@
+// Check the errors reported for invalid definitions. Run with `TRYBUILD=overwrite` to regenerate
+// the expected output after changing a diagnostic.
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,11 @@
+use error_def::error_def;
+
+error_def! {
+    #[error_def(accessors)]
+    FileError {
+        IOError(std::io::Error) => "I/O error",
+        IoError(std::io::Error) => "I/O error",
+    }
+}
+
+fn main() {}
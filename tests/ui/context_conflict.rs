@@ -0,0 +1,13 @@
+use error_def::error_def;
+
+error_def! {
+    #[error_def(context)]
+    FileError {
+        IOError { #[source] cause: std::io::Error } => "I/O error",
+        IoError { #[source] cause: std::io::Error } => "I/O error",
+        NotFound => "Not found",
+        NotFOUND => "Not found",
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use error_def::error_def;
+
+error_def! {
+    CodedError {
+        NotFound = 404 => "Not found",
+        Gone = 404 => "Gone",
+    }
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use error_def::error_def;
+
+error_def! {
+    DuplicateError {
+        Missing { name: String, name: String } => "Missing",
+    }
+}
+
+fn main() {}
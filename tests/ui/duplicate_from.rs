@@ -0,0 +1,10 @@
+use error_def::error_def;
+
+error_def! {
+    IoError {
+        Read { #[from] cause: std::io::Error } => "Read failed",
+        Write { #[from] cause: std::io::Error } => "Write failed",
+    }
+}
+
+fn main() {}
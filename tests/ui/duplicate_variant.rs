@@ -0,0 +1,10 @@
+use error_def::error_def;
+
+error_def! {
+    DuplicateError {
+        Missing => "Missing",
+        Missing => "Missing again",
+    }
+}
+
+fn main() {}
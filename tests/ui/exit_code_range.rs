@@ -0,0 +1,13 @@
+use error_def::error_def;
+
+error_def! {
+    #[error_def(exit_code = 1)]
+    CliError {
+        #[exit_code = 0]
+        Success => "Not an error",
+        #[exit_code = 256]
+        Overflow => "Too big",
+    }
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use error_def::error_def;
+
+#[derive(Debug)]
+struct NotAnError;
+
+error_def! {
+    ConversionError {
+        Failed { #[from] cause: NotAnError } => "Failed",
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use error_def::error_def;
+
+fn parse(s: String) -> std::num::ParseIntError {
+    s.parse::<u8>().unwrap_err()
+}
+
+error_def! {
+    ParseError {
+        Invalid { #[from(with = parse)] cause: std::num::ParseIntError } => "Invalid",
+    }
+}
+
+fn main() {}
@@ -0,0 +1,3 @@
+# Catalog for tests/ui/missing_catalog_key.rs, which has no message for `Timeout`.
+
+localized-error-not-found = Not found
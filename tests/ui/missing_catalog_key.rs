@@ -0,0 +1,13 @@
+use error_def::error_def;
+
+// Catalog paths are relative to the crate being built, which trybuild puts in
+// target/tests/trybuild/error_def.
+error_def! {
+    #[error_def(catalogs = "../../../../tests/ui/locales", default_locale = "en")]
+    LocalizedError {
+        NotFound => "Not found",
+        Timeout => "Timed out",
+    }
+}
+
+fn main() {}
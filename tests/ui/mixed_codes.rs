@@ -0,0 +1,12 @@
+use error_def::error_def;
+
+error_def! {
+    CodedError {
+        NotFound = 404u16 => "Not found",
+        Gone = 410u32 => "Gone",
+        Teapot = "E418" => "I'm a teapot",
+        Unknown => "Unknown",
+    }
+}
+
+fn main() {}
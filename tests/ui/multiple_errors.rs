@@ -0,0 +1,13 @@
+use error_def::error_def;
+
+// Every broken variant is reported, not just the first.
+error_def! {
+    BrokenError {
+        Missing => ,
+        Present => "Present",
+        Unknown { id: u32, id: u32 } => "Unknown",
+        Present => "Present again",
+    }
+}
+
+fn main() {}
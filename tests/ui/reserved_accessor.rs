@@ -0,0 +1,11 @@
+use error_def::error_def;
+
+error_def! {
+    #[error_def(accessors)]
+    PathError {
+        Super => "Parent directory",
+        Loop => "Loop",
+    }
+}
+
+fn main() {}